@@ -0,0 +1,158 @@
+//! Export of computation graphs in the DOT language of GraphViz.
+//!
+//! Each tensor of the graph is a node labelled with its name, the name of the operation that
+//! computed it and its shape, with an edge from each parent. Operation results are drawn as
+//! boxes and leaves as ellipses. Render with e.g. `dot -Tsvg graph.dot -o graph.svg`.
+
+use std::{collections::HashMap, fmt::Write as _, io, path::Path};
+
+use crate::{
+    element::Element,
+    error::TensorError,
+    tensor::{topological_order, Tensor, TensorRef},
+};
+
+/// Settings for `to_dot`.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DotOptions {
+    /// Add the L2 norm of the gradient of each tensor that has one to its label.
+    ///
+    /// A backward pass frees the graph unless run with `retain_graph`, leaving only the output
+    /// to export: run it with `retain_graph` to see the gradients of the whole graph.
+    pub grad_norms: bool,
+}
+
+/// The graph behind `output` in the DOT language, panicking where `try_to_dot` would return an
+/// error.
+#[allow(dead_code)]
+pub fn to_dot<T: Element>(output: &TensorRef<T>, options: DotOptions) -> String {
+    try_to_dot(output, options).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// The graph behind `output` in the DOT language. Fails when a tensor of the graph is mutably
+/// borrowed.
+#[allow(dead_code)]
+pub fn try_to_dot<T: Element>(
+    output: &TensorRef<T>,
+    options: DotOptions,
+) -> Result<String, TensorError> {
+    // Only the tensors of the output's element type are drawn, not the graphs behind casts.
+    let nodes: Vec<TensorRef<T>> = topological_order(std::slice::from_ref(output))?
+        .iter()
+        .filter_map(|node| node.as_any().downcast_ref::<TensorRef<T>>().cloned())
+        .collect();
+    let ids: HashMap<usize, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(index, node)| (node.id(), index))
+        .collect();
+
+    let mut dot = String::from("digraph {\n");
+    for (index, node) in nodes.iter().enumerate() {
+        let tensor = node.try_borrow()?;
+        let shape = if tensor.operation.is_some() {
+            "box"
+        } else {
+            "ellipse"
+        };
+        let label = escape(&label(&tensor, options)?);
+        writeln!(dot, "    n{index} [shape={shape}, label=\"{label}\"];")
+            .expect("writes to a String");
+    }
+    for (index, node) in nodes.iter().enumerate() {
+        for parent in &node.try_borrow()?.parents {
+            writeln!(dot, "    n{} -> n{index};", ids[&parent.id()]).expect("writes to a String");
+        }
+    }
+    dot.push_str("}\n");
+
+    Ok(dot)
+}
+
+/// Writes the graph behind `output` to the DOT file at `path`. The errors of `try_to_dot` are
+/// returned as `io::Error`s.
+#[allow(dead_code)]
+pub fn write_dot<T: Element>(
+    output: &TensorRef<T>,
+    path: impl AsRef<Path>,
+    options: DotOptions,
+) -> io::Result<()> {
+    let dot = try_to_dot(output, options).map_err(|err| io::Error::other(err.to_string()))?;
+    std::fs::write(path, dot)
+}
+
+#[allow(dead_code)]
+fn label<T: Element>(tensor: &Tensor<T>, options: DotOptions) -> Result<String, TensorError> {
+    let mut lines = vec![tensor.name.clone().unwrap_or_else(|| "tensor".to_string())];
+
+    lines.push(match &tensor.operation {
+        Some(operation) => operation.name().to_string(),
+        None => "leaf".to_string(),
+    });
+    lines.push(format!("{:?}", tensor.arr.shape()));
+
+    if let (true, Some(grad)) = (options.grad_norms, &tensor.grad) {
+        let norm = grad
+            .try_borrow()?
+            .arr
+            .iter()
+            .fold(T::zero(), |sum, &v| sum + v * v)
+            .sqrt();
+        lines.push(format!("|grad| = {norm}"));
+    }
+
+    Ok(lines.join("\n"))
+}
+
+#[allow(dead_code)]
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use crate::{error::TensorError, matmul, sum, tensor, tensor::BackwardOptions};
+
+    use super::{to_dot, write_dot, DotOptions};
+
+    #[test]
+    fn dot_lists_nodes_and_edges() {
+        let w = tensor!(array![[3.0, 4.0]]);
+        let x = tensor!(array![[1.0], [1.0]]);
+        let y = sum!(matmul!(w, x));
+
+        // The graph has to outlive the backward pass to be exported with the gradients.
+        let options = BackwardOptions {
+            retain_graph: true,
+            ..Default::default()
+        };
+        y.backward_with(None, options);
+        let dot = to_dot(&y, DotOptions { grad_norms: true });
+
+        assert!(dot.starts_with("digraph {\n"));
+        assert!(dot.contains("\\nmatmul\\n[1, 1]\\n|grad| = 1\""));
+        assert!(dot.contains(
+            "[shape=ellipse, label=\"tensor\\nleaf\\n[1, 2]\\n|grad| = 1.4142135623730951\"]"
+        ));
+        assert_eq!(dot.matches(" -> ").count(), 3);
+    }
+
+    #[test]
+    fn write_dot_returns_borrow_errors() {
+        let x = tensor!(array![[1.0, 2.0]]);
+        let y = sum!(x);
+        let path = std::env::temp_dir().join("write_dot_returns_borrow_errors.dot");
+
+        let _x_borrow = x.borrow_mut();
+        let err = write_dot(&y, &path, DotOptions::default()).unwrap_err();
+
+        assert_eq!(err.to_string(), TensorError::BorrowConflict.to_string());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,19 @@
+use ndarray::NdFloat;
+use num_traits::{FromPrimitive, NumCast};
+
+/// Scalar type stored by tensors. Implemented for `f32` and `f64`.
+pub trait Element: NdFloat + FromPrimitive {
+    /// Converts an `f64` constant into this element type, rounding if needed.
+    fn cast_from(value: f64) -> Self {
+        Self::from_f64(value).expect("f64 constants are representable by every element type")
+    }
+
+    /// Converts a value of another element type into this one, rounding if needed.
+    fn convert<S: Element>(value: S) -> Self {
+        <Self as NumCast>::from(value).expect("element types convert between each other")
+    }
+}
+
+impl Element for f32 {}
+
+impl Element for f64 {}
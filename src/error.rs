@@ -0,0 +1,78 @@
+use std::fmt;
+
+/// Errors reported by the fallible (`try_`) side of the API. The infallible methods and the
+/// operation macros panic with the same messages instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TensorError {
+    /// The operands of `op` have shapes it can't combine.
+    ShapeMismatch {
+        op: String,
+        lhs: Vec<usize>,
+        rhs: Vec<usize>,
+    },
+    /// A tensor was already borrowed in a way that conflicts with the access that was attempted.
+    BorrowConflict,
+    /// A gradient was requested from a tensor that hasn't received one.
+    MissingGradient { name: Option<String> },
+    /// A backward pass reached a tensor whose graph was freed by an earlier pass run without
+    /// `retain_graph`.
+    GraphFreed { name: Option<String> },
+    /// `backward` was called without a seed gradient on a tensor holding more than one value.
+    NonScalarSeed { shape: Vec<usize> },
+    /// An indexing operation `op` was given a position outside an axis of length `len`, or a
+    /// value that isn't a position at all.
+    IndexOutOfBounds { op: String, index: i64, len: usize },
+    /// The `CustomOp` named `op` was used where it needs a `rule` it wasn't given, e.g. the
+    /// batching rule under `vmap`.
+    MissingRule { op: String, rule: String },
+    /// `trace` reached an operation `op` reading a foreign parent that the traced function
+    /// computed, which a replay couldn't recompute from the new inputs.
+    Untraceable { op: String },
+}
+
+impl fmt::Display for TensorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TensorError::ShapeMismatch { op, lhs, rhs } => {
+                write!(f, "{op}: shapes {lhs:?} and {rhs:?} are incompatible")
+            }
+            TensorError::BorrowConflict => {
+                write!(f, "tensor is already borrowed in a conflicting way")
+            }
+            TensorError::MissingGradient { name: Some(name) } => {
+                write!(f, "tensor {name} has no gradient")
+            }
+            TensorError::MissingGradient { name: None } => write!(f, "tensor has no gradient"),
+            TensorError::GraphFreed { name: Some(name) } => write!(
+                f,
+                "graph of tensor {name} was freed by a previous backward pass, use retain_graph to \
+                 back-propagate through it again"
+            ),
+            TensorError::GraphFreed { name: None } => write!(
+                f,
+                "graph was freed by a previous backward pass, use retain_graph to back-propagate \
+                 through it again"
+            ),
+            TensorError::NonScalarSeed { shape } => write!(
+                f,
+                "backward without a seed gradient needs a single-valued tensor, got shape {shape:?}"
+            ),
+            TensorError::IndexOutOfBounds { op, index, len } => {
+                write!(
+                    f,
+                    "{op}: index {index} is out of bounds for an axis of length {len}"
+                )
+            }
+            TensorError::MissingRule { op, rule } => {
+                write!(f, "custom op `{op}` has no {rule} rule")
+            }
+            TensorError::Untraceable { op } => write!(
+                f,
+                "trace: {op} reads a tensor of another element type computed by the traced \
+                 function, which can't be replayed"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TensorError {}
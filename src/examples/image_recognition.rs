@@ -6,11 +6,12 @@ use rand_distr::{Distribution, Normal};
 
 use image::{GrayImage, Luma};
 
-use crate::{add, ln, prod, softmax, sum, tensor::TensorRef};
-use crate::{matmul, relu, square, sub, tensor};
+use crate::{add, grad_mode::InferenceModeGuard, ln, prod, softmax, sum, tensor::TensorRef};
+use crate::{matmul, relu, reshape, slice, square, sub, tensor};
+use crate::{trace::trace, vmap::vmap};
 
 const EPOCHS: usize = 100;
-const LR: f64 = 3e-1;
+const LR: f32 = 3e-1;
 const TRAIN_SIZE: usize = 1000;
 const TEST_SIZE: usize = 10;
 
@@ -27,17 +28,17 @@ pub fn perform_image_recognition() {
         .test_set_length(TEST_SIZE as u32)
         .finalize();
 
-    let train_images_flat: Array2<f64> = Array2::from_shape_vec(
+    let train_images_flat: Array2<f32> = Array2::from_shape_vec(
         (TRAIN_SIZE, 28 * 28),
         trn_img
             .clone()
             .into_iter()
-            .map(|x| x as f64 / 255.0)
+            .map(|x| x as f32 / 255.0)
             .collect(),
     )
     .expect("Error converting training images to flat Array2 struct");
 
-    let train_labels_one_hot: Array2<f64> = Array2::from_shape_fn((TRAIN_SIZE, 10), |(i, j)| {
+    let train_labels_one_hot: Array2<f32> = Array2::from_shape_fn((TRAIN_SIZE, 10), |(i, j)| {
         if trn_lbl[i] as usize == j {
             1.0
         } else {
@@ -49,14 +50,18 @@ pub fn perform_image_recognition() {
 
     mnist_mlp.train(EPOCHS, LR);
 
+    let test_images = tensor!(Array2::from_shape_vec(
+        (TEST_SIZE, 28 * 28),
+        tst_img.iter().map(|x| *x as f32 / 255.0).collect(),
+    )
+    .expect("Error converting test images to flat Array2 struct"));
+
     let mut correct_guesses = 0;
+    let _inference = InferenceModeGuard::new();
 
     for i in 1..TEST_SIZE {
-        let test_image: Vec<f64> = tst_img[784 * (i - 1)..784 * i]
-            .iter()
-            .map(|x| *x as f64 / 255.0)
-            .collect();
-        let pred_logits = mnist_mlp.forward(tensor!(test_image));
+        let test_image = reshape!(slice!(test_images, 0, i - 1..i), &[28 * 28, 1]);
+        let pred_logits = mnist_mlp.forward(test_image);
         let pred_probs = softmax!(pred_logits);
 
         let model_predicted_label = pred_probs
@@ -84,10 +89,10 @@ pub fn perform_image_recognition() {
             .borrow()
             .arr
             .iter()
-            .collect::<Vec<&f64>>()
+            .collect::<Vec<&f32>>()
             .into_iter()
             .map(|x| *x * 100.0)
-            .collect::<Vec<f64>>()
+            .collect::<Vec<f32>>()
             .iter()
             .enumerate()
         {
@@ -103,15 +108,15 @@ pub fn perform_image_recognition() {
 
 pub struct MnistMlp<F> {
     mlp: Mlp<F>,
-    images: Array2<f64>,
-    labels: Array2<f64>,
+    images: Array2<f32>,
+    labels: Array2<f32>,
 }
 
 impl<F> MnistMlp<F>
 where
-    F: Fn(TensorRef) -> TensorRef,
+    F: Fn(TensorRef<f32>) -> TensorRef<f32>,
 {
-    pub fn new(activation_fn: F, images: Array2<f64>, labels: Array2<f64>) -> Self {
+    pub fn new(activation_fn: F, images: Array2<f32>, labels: Array2<f32>) -> Self {
         MnistMlp {
             mlp: Mlp::new(activation_fn),
             images,
@@ -119,46 +124,42 @@ where
         }
     }
 
-    pub fn train(&mut self, epochs: usize, lr: f64) {
+    pub fn train(&mut self, epochs: usize, lr: f32) {
         let params = self.mlp.parameters();
         self.gradient_descent(epochs, lr, &params);
     }
 
-    pub fn forward(&self, x: TensorRef) -> TensorRef {
+    pub fn forward(&self, x: TensorRef<f32>) -> TensorRef<f32> {
         self.mlp.forward(x)
     }
 
-    fn cross_entropy_loss(&self, _inputs: &[TensorRef]) -> TensorRef {
-        let mut total_loss = tensor!(0.0);
-        let num_samples = self.images.shape()[0] as f64;
-
-        for (image, label_one_hot_arr) in self.images.outer_iter().zip(self.labels.outer_iter()) {
-            let image_vec: Vec<f64> = image.iter().map(|&x| x).collect();
-            let label_one_hot = label_one_hot_arr
-                .to_owned()
-                .into_shape_clone((10, 1))
-                .unwrap();
-
-            let predicted = self.mlp.forward(tensor!(image_vec));
-            let predicted_probs = softmax!(predicted);
+    fn cross_entropy_loss(&self, _inputs: &[TensorRef<f32>]) -> TensorRef<f32> {
+        let num_samples = self.images.shape()[0];
+        // Each example becomes a column vector.
+        let images = reshape!(tensor!(self.images.clone()), &[num_samples, 28 * 28, 1]);
+        let labels = reshape!(tensor!(self.labels.clone()), &[num_samples, 10, 1]);
 
-            let log_probs = ln!(predicted_probs);
-            let neg_log_probs = prod!(log_probs, -1.0);
+        let losses = vmap(
+            |batch| {
+                let predicted_probs = softmax!(self.mlp.forward(batch[0].clone()));
 
-            let loss = sum!(prod!(tensor!(neg_log_probs), tensor!(label_one_hot)));
+                let log_probs = ln!(predicted_probs);
+                let neg_log_probs = prod!(log_probs, -1.0);
 
-            total_loss = add!(total_loss, loss)
-        }
+                sum!(prod!(neg_log_probs, batch[1]))
+            },
+            &[images, labels],
+        );
 
-        prod!(total_loss, 1.0 / num_samples)
+        prod!(sum!(losses), 1.0 / num_samples as f32)
     }
 
-    fn loss(&self, _inputs: &[TensorRef]) -> TensorRef {
+    fn loss(&self, _inputs: &[TensorRef<f32>]) -> TensorRef<f32> {
         let mut total_loss = tensor!(0.0);
-        let num_samples = self.images.shape()[0] as f64;
+        let num_samples = self.images.shape()[0] as f32;
 
         for (image, label_one_hot_arr) in self.images.outer_iter().zip(self.labels.outer_iter()) {
-            let image_vec: Vec<f64> = image.iter().map(|&x| x).collect();
+            let image_vec: Vec<f32> = image.iter().copied().collect();
             let label_one_hot = label_one_hot_arr
                 .to_owned()
                 .into_shape_clone((10, 1))
@@ -174,16 +175,29 @@ where
         sum!(total_loss)
     }
 
-    fn gradient_descent(&self, n_epochs: usize, lr: f64, inputs: &[TensorRef]) {
+    fn gradient_descent(&self, n_epochs: usize, lr: f32, inputs: &[TensorRef<f32>]) {
+        // The loss graph is the same every epoch: record it once, optimize it and replay it.
+        let mut loss_trace = trace(|_| self.cross_entropy_loss(&[]), &[]);
+        let traced_operations = loss_trace.num_operations();
+        loss_trace.optimize();
+        println!(
+            "Traced the loss into {} operations, {} after optimization",
+            traced_operations,
+            loss_trace.num_operations()
+        );
+
         for epoch in 0..n_epochs {
             for input in inputs {
                 input.zero_grad();
             }
 
-            let loss = self.cross_entropy_loss(&[]);
-            loss.backward(None);
+            let loss = loss_trace.run(&[]);
+            if let Err(err) = loss.try_backward(None) {
+                println!("Epoch {}: backward pass failed: {err}", epoch + 1);
+                return;
+            }
 
-            let current_loss: Vec<f64> = loss.borrow().arr.iter().map(|x| *x).collect();
+            let current_loss: Vec<f32> = loss.borrow().arr.iter().copied().collect();
             assert!(current_loss.len() == 1, "loss value must be a scalar!");
 
             let current_loss_value = current_loss[0];
@@ -206,17 +220,17 @@ where
 
 pub struct Mlp<F> {
     activation_fn: F,
-    w0: TensorRef,
-    b0: TensorRef,
-    w1: TensorRef,
-    b1: TensorRef,
-    w2: TensorRef,
-    b2: TensorRef,
+    w0: TensorRef<f32>,
+    b0: TensorRef<f32>,
+    w1: TensorRef<f32>,
+    b1: TensorRef<f32>,
+    w2: TensorRef<f32>,
+    b2: TensorRef<f32>,
 }
 
 impl<F> Mlp<F>
 where
-    F: Fn(TensorRef) -> TensorRef,
+    F: Fn(TensorRef<f32>) -> TensorRef<f32>,
 {
     pub fn new(activation_fn: F) -> Self {
         // Input Layer: 784 features (28x28 flattened image)
@@ -244,18 +258,17 @@ where
         }
     }
 
-    fn forward(&self, x: TensorRef) -> TensorRef {
+    fn forward(&self, x: TensorRef<f32>) -> TensorRef<f32> {
         let z0 = add!(matmul!(self.w0, x), self.b0);
         let h0 = (self.activation_fn)(z0);
 
         let z1 = add!(matmul!(self.w1, h0), self.b1);
         let h1 = (self.activation_fn)(z1);
 
-        let y = add!(matmul!(self.w2, h1), self.b2);
-        y
+        add!(matmul!(self.w2, h1), self.b2)
     }
 
-    fn parameters(&self) -> Vec<TensorRef> {
+    fn parameters(&self) -> Vec<TensorRef<f32>> {
         vec![
             self.w0.clone(),
             self.b0.clone(),
@@ -266,11 +279,11 @@ where
         ]
     }
 
-    fn init_matrix(rows: usize, cols: usize) -> Array2<f64> {
+    fn init_matrix(rows: usize, cols: usize) -> Array2<f32> {
         let normal = Normal::new(0.0, 1.0).unwrap();
         let mut rng = rng();
 
-        let data: Vec<f64> = (0..rows * cols)
+        let data: Vec<f32> = (0..rows * cols)
             .map(|_| normal.sample(&mut rng) * 0.1)
             .collect();
 
@@ -281,7 +294,7 @@ where
 fn save_img_to_disk(img_data: &[u8], name: &str) {
     let mut img = GrayImage::new(28, 28);
 
-    for (i, pixel) in img_data.into_iter().enumerate() {
+    for (i, pixel) in img_data.iter().enumerate() {
         let x = (i % 28) as u32;
         let y = (i / 28) as u32;
         img.put_pixel(x, y, Luma([*pixel]));
@@ -10,7 +10,9 @@ use plotlib::{
 use rand::rng;
 use rand_distr::{Distribution, Normal};
 
-use crate::{add, examples::float_range, matmul, prod, sub, tanh, tensor::TensorRef};
+use crate::{
+    examples::float_range, functional::value_and_grad, grad_mode::no_grad, tanh, tensor::TensorRef,
+};
 use crate::{square, tensor};
 
 const EPOCHS: usize = 500;
@@ -73,7 +75,7 @@ where
             .xs
             .iter()
             .map(|x| {
-                let y = self.mlp.forward(tensor!(*x));
+                let y = no_grad(|| self.mlp.forward(tensor!(*x)));
                 let arr = y.borrow();
                 let values = arr.arr.rows().into_iter().flatten().collect::<Vec<&f64>>();
                 *values[0]
@@ -120,9 +122,8 @@ where
 
         for (xi, yi) in zip(&self.xs, &self.ys) {
             let predicted = self.mlp.forward(tensor!(*xi));
-            let diff = sub!(tensor!(*yi), predicted);
-            let loss = prod!(square!(diff), tensor!(1.0 / xs_len));
-            total_loss = add!(total_loss, loss);
+            let diff = tensor!(*yi) - predicted;
+            total_loss += square!(diff) * (1.0 / xs_len);
         }
 
         total_loss
@@ -130,27 +131,18 @@ where
 
     fn gradient_descent(&self, n_epochs: usize, lr: f64, inputs: &[TensorRef]) {
         for epoch in 0..n_epochs {
-            for input in inputs {
-                input.borrow_mut().zero_grad();
-            }
-
-            let loss = self.loss(&[]);
-            loss.clone().borrow_mut().backward(None);
+            let (loss, grads) = value_and_grad(|inputs| self.loss(inputs), inputs);
 
-            let current_loss: Vec<f64> = loss.borrow().arr.iter().map(|x| *x).collect();
+            let current_loss: Vec<f64> = loss.borrow().arr.iter().copied().collect();
             assert!(current_loss.len() == 1, "loss value must be a scalar!");
 
             let current_loss_value = current_loss[0];
 
-            for input in inputs {
+            for (input, grad) in inputs.iter().zip(grads) {
                 let mut input_borrow = input.borrow_mut();
-                if let Some(grad_tensor_rc) = &input_borrow.grad {
-                    let new_arr_value = &input_borrow.arr - (lr * &grad_tensor_rc.borrow().arr);
+                let new_arr_value = &input_borrow.arr - (lr * &grad.borrow().arr);
 
-                    input_borrow.set_arr(new_arr_value);
-                } else {
-                    println!("Warning: Parameter did not receive a gradient.");
-                }
+                input_borrow.set_arr(new_arr_value);
             }
 
             println!("Epoch {}: LOSS: {:.6}", epoch + 1, current_loss_value);
@@ -192,14 +184,13 @@ where
     }
 
     fn forward(&self, x: TensorRef) -> TensorRef {
-        let z0 = add!(matmul!(self.w0, x), self.b0);
+        let z0 = self.w0.matmul(&x) + &self.b0;
         let h0 = (self.activation_fn)(z0);
 
-        let z1 = add!(matmul!(self.w1, h0), self.b1);
+        let z1 = self.w1.matmul(&h0) + &self.b1;
         let h1 = (self.activation_fn)(z1);
 
-        let y = add!(matmul!(self.w2, h1), self.b2);
-        y
+        self.w2.matmul(&h1) + &self.b2
     }
 
     fn parameters(&self) -> Vec<TensorRef> {
@@ -3,7 +3,7 @@ use plotlib::repr::Plot;
 use plotlib::style::{LineStyle, PointStyle};
 use plotlib::view::ContinuousView;
 
-use crate::{add, prod, tensor};
+use crate::{add, functional::value_and_grad, grad_mode::inference_mode, prod, tensor};
 use crate::{sin, tensor::TensorRef};
 
 const EPOCHS: usize = 1000;
@@ -17,7 +17,7 @@ pub fn perform_sin_regression() {
     let original_y: Vec<f64> = original_x
         .iter()
         .map(|xv| {
-            let loss = sin_objective_fn(&[tensor!(*xv)]);
+            let loss = inference_mode(|| sin_objective_fn(&[tensor!(*xv)]));
             let arr = loss.borrow();
             let values = arr.arr.rows().into_iter().flatten().collect::<Vec<&f64>>();
             *values[0]
@@ -53,14 +53,9 @@ where
     let mut input_vals = Vec::new();
 
     for epoch in 0..n_epochs {
-        for input in inputs {
-            input.borrow_mut().zero_grad();
-        }
-
-        let loss = objective_fn(inputs);
-        loss.clone().borrow_mut().backward(None);
+        let (loss, grads) = value_and_grad(&objective_fn, inputs);
 
-        let current_loss: Vec<f64> = loss.borrow().arr.iter().map(|x| *x).collect();
+        let current_loss: Vec<f64> = loss.borrow().arr.iter().copied().collect();
         assert!(current_loss.len() == 1, "loss value must be a scalar!");
         let current_loss_value = current_loss[0];
         loss_vals.push(current_loss_value);
@@ -81,15 +76,11 @@ where
 
         input_vals.push(input_val);
 
-        for input in inputs {
+        for (input, grad) in inputs.iter().zip(grads) {
             let mut input_borrow = input.borrow_mut();
-            if let Some(grad_tensor_rc) = &input_borrow.grad {
-                let new_arr_value = &input_borrow.arr - (lr * &grad_tensor_rc.borrow().arr);
+            let new_arr_value = &input_borrow.arr - (lr * &grad.borrow().arr);
 
-                input_borrow.set_arr(new_arr_value);
-            } else {
-                println!("Warning: Parameter did not receive a gradient.");
-            }
+            input_borrow.set_arr(new_arr_value);
         }
 
         println!("Epoch {}: LOSS: {:.6}", epoch + 1, current_loss_value);
@@ -100,15 +91,11 @@ where
 
 fn plot_fn(original_x: &[f64], original_y: &[f64], out_x: &[f64], out_y: &[f64]) {
     let data: Vec<(f64, f64)> = original_x
-        .into_iter()
+        .iter()
         .zip(original_y)
         .map(|(xi, yi)| (*xi, *yi))
         .collect();
-    let train_data: Vec<(f64, f64)> = out_x
-        .into_iter()
-        .zip(out_y)
-        .map(|(xi, yi)| (*xi, *yi))
-        .collect();
+    let train_data: Vec<(f64, f64)> = out_x.iter().zip(out_y).map(|(xi, yi)| (*xi, *yi)).collect();
 
     let line = Plot::new(data)
         .legend("Points".to_string())
@@ -0,0 +1,187 @@
+//! Forward-mode differentiation.
+//!
+//! Inside `jvp`, every tensor computed by an operation also gets a tangent: the directional
+//! derivative of its value along the tangents given for the inputs, obtained from the
+//! operation's `Operation::jvp` rule as soon as the tensor is built. A single pass then yields
+//! the derivative of every output along one input direction, which is cheaper than reverse mode
+//! for functions with few inputs and many outputs.
+
+use std::cell::Cell;
+
+use ndarray::ArrayD;
+
+use crate::{
+    element::Element,
+    operation::{MultiOperation, Operation},
+    tensor,
+    tensor::{TensorBuilder, TensorRef},
+};
+
+thread_local! {
+    static FORWARD_MODE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether operations on this thread compute the tangents of their results.
+pub fn is_forward_mode() -> bool {
+    FORWARD_MODE.with(|f| f.get())
+}
+
+/// Sets whether tangents are computed until dropped, then restores the previous setting.
+pub struct ForwardModeGuard {
+    previous: bool,
+}
+
+impl ForwardModeGuard {
+    pub fn new(enabled: bool) -> Self {
+        let previous = FORWARD_MODE.with(|f| f.replace(enabled));
+        ForwardModeGuard { previous }
+    }
+}
+
+impl Drop for ForwardModeGuard {
+    fn drop(&mut self) {
+        FORWARD_MODE.with(|f| f.set(self.previous));
+    }
+}
+
+/// Tangent of the result of `operation` applied to `args`, from the tangents attached to them.
+///
+/// The tangent is computed with operations so it can be differentiated in turn, but forward
+/// mode is paused meanwhile: the tangent computations don't get tangents of their own.
+pub fn output_tangent<T: Element>(
+    operation: &dyn Operation<T>,
+    args: &[TensorRef<T>],
+) -> Option<TensorRef<T>> {
+    let tangents: Vec<Option<TensorRef<T>>> = args
+        .iter()
+        .map(|arg| arg.borrow().tangent.clone())
+        .collect();
+
+    let _paused = ForwardModeGuard::new(false);
+    operation.jvp(&tangents, args)
+}
+
+/// Same as `output_tangent` for the `outputs` of an operation with several outputs.
+pub fn output_tangents<T: Element>(
+    operation: &dyn MultiOperation<T>,
+    args: &[TensorRef<T>],
+    outputs: &[TensorRef<T>],
+) -> Vec<Option<TensorRef<T>>> {
+    let tangents: Vec<Option<TensorRef<T>>> = args
+        .iter()
+        .map(|arg| arg.borrow().tangent.clone())
+        .collect();
+
+    let _paused = ForwardModeGuard::new(false);
+    operation.jvp(&tangents, args, outputs)
+}
+
+/// Evaluates `f` at `primals` and returns its output along with the derivative of the output in
+/// the direction of `tangents`, i.e. the Jacobian-vector product.
+///
+/// `f` receives new leaf tensors holding the values of `primals`, so the tangents don't stick to
+/// the caller's tensors. Its graph is recorded as usual; run `jvp` under `no_grad` when only the
+/// tangent is needed.
+#[allow(dead_code)]
+pub fn jvp<T, F>(
+    f: F,
+    primals: &[TensorRef<T>],
+    tangents: &[TensorRef<T>],
+) -> (TensorRef<T>, TensorRef<T>)
+where
+    T: Element,
+    F: FnOnce(&[TensorRef<T>]) -> TensorRef<T>,
+{
+    assert_eq!(
+        primals.len(),
+        tangents.len(),
+        "jvp: expected one tangent per primal"
+    );
+
+    let inputs: Vec<TensorRef<T>> = primals
+        .iter()
+        .zip(tangents)
+        .map(|(primal, tangent)| {
+            let primal = primal.borrow();
+            let tangent_shape = tangent.borrow().arr.shape().to_vec();
+            assert_eq!(
+                primal.arr.shape(),
+                tangent_shape.as_slice(),
+                "jvp: tangent shape doesn't match its primal"
+            );
+
+            let mut input = TensorBuilder::new(primal.arr.to_owned())
+                .requires_grad(primal.requires_grad)
+                .build();
+            input.tangent = Some(tangent.clone());
+            TensorRef::new(input)
+        })
+        .collect();
+
+    let output = {
+        let _forward_mode = ForwardModeGuard::new(true);
+        f(&inputs)
+    };
+
+    let output_tangent = output.borrow().tangent.clone();
+    let output_tangent = output_tangent
+        .unwrap_or_else(|| tensor!(ArrayD::<T>::zeros(output.borrow().arr.raw_dim())));
+
+    (output, output_tangent)
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, ArcArray, Array1, IxDyn};
+
+    use crate::{add, grad_mode::no_grad, matmul, prod, sin, softmax, sum, tensor};
+
+    use super::jvp;
+
+    #[test]
+    fn jvp_sweeps_elementwise_function_in_one_pass() {
+        let xs = Array1::linspace(0.0, 6.0, 7);
+        let x = tensor!(xs.clone());
+        let ones = tensor!(Array1::<f64>::ones(7));
+
+        let (y, dy) = no_grad(|| jvp(|v| sin!(add!(prod!(2.0, v[0]), 0.5)), &[x], &[ones]));
+
+        let expected_y = xs.mapv(|x| (2.0 * x + 0.5).sin());
+        let expected_dy = xs.mapv(|x| 2.0 * (2.0 * x + 0.5).cos());
+        let max_error = |actual: &ArcArray<f64, IxDyn>, expected: Array1<f64>| {
+            (actual - &expected.into_dyn()).fold(0.0_f64, |m, e| m.max(e.abs()))
+        };
+        assert!(max_error(&y.borrow().arr, expected_y) < 1e-12);
+        assert!(max_error(&dy.borrow().arr, expected_dy) < 1e-12);
+    }
+
+    #[test]
+    fn jvp_combines_tangents_of_several_inputs() {
+        let w = tensor!(array![[1.0, 2.0], [3.0, 4.0]]);
+        let x = tensor!(array![[1.0], [-1.0]]);
+        let dw = tensor!(array![[1.0, 0.0], [0.0, 0.0]]);
+        let dx = tensor!(array![[0.0], [1.0]]);
+
+        let (_, dy) = jvp(|v| matmul!(v[0], v[1]), &[w, x], &[dw, dx]);
+
+        // dw @ x + w @ dx
+        assert_eq!(dy.borrow().arr, array![[3.0], [4.0]].into_dyn());
+    }
+
+    #[test]
+    fn jvp_of_constant_output_is_zero() {
+        let x = tensor!(array![1.0, 2.0, 3.0]);
+        let dx = tensor!(array![1.0, 1.0, 1.0]);
+
+        // Shifting every input by the same amount doesn't change softmax.
+        let (_, d_sum) = jvp(
+            |v| sum!(prod!(softmax!(v[0]), 1.0)),
+            std::slice::from_ref(&x),
+            &[dx],
+        );
+        assert!(d_sum.borrow().arr.iter().all(|v: &f64| v.abs() < 1e-12));
+
+        let (_, d_const) = jvp(|_| tensor!(1.0), &[x], &[tensor!(array![1.0, 0.0, 0.0])]);
+        assert_eq!(d_const.borrow().arr[[0, 0]], 0.0);
+    }
+}
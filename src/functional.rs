@@ -0,0 +1,228 @@
+//! Functional entry points over closures of tensors, built on `Tensor::backward`.
+//!
+//! They differentiate with respect to the tensors passed in `inputs`, which the closure may use
+//! through its argument or capture directly, as the model parameters in the examples are. The
+//! backward passes are isolated, see `TensorRef::try_grads_of`: the gradients of the inputs are
+//! returned rather than accumulated, and no tensor's `grad` changes, not even those of captured
+//! tensors or intermediate results. The graph is freed once the last pass is done.
+
+use ndarray::{Array2, ArrayD};
+
+use crate::{
+    element::Element,
+    tensor,
+    tensor::{BackwardOptions, TensorRef},
+};
+
+/// Gradients of the single-valued output of `f` with respect to each of `inputs`, zeros for the
+/// inputs it doesn't depend on.
+#[allow(dead_code)]
+pub fn grad<T, F>(f: F, inputs: &[TensorRef<T>]) -> Vec<TensorRef<T>>
+where
+    T: Element,
+    F: FnOnce(&[TensorRef<T>]) -> TensorRef<T>,
+{
+    value_and_grad(f, inputs).1
+}
+
+/// Output of `f` along with its gradients, as given by `grad`.
+pub fn value_and_grad<T, F>(f: F, inputs: &[TensorRef<T>]) -> (TensorRef<T>, Vec<TensorRef<T>>)
+where
+    T: Element,
+    F: FnOnce(&[TensorRef<T>]) -> TensorRef<T>,
+{
+    let value = f(inputs);
+    let grads = gradients(&value, None, inputs, BackwardOptions::default());
+
+    (value, grads)
+}
+
+/// Jacobian of the output of `f` with respect to each of `inputs`: for an output of `m` values
+/// and an input of `n` values, an (m, n) matrix whose row `i` is the gradient of the `i`-th
+/// output value. Values are numbered in row-major order.
+#[allow(dead_code)]
+pub fn jacobian<T, F>(f: F, inputs: &[TensorRef<T>]) -> Vec<TensorRef<T>>
+where
+    T: Element,
+    F: FnOnce(&[TensorRef<T>]) -> TensorRef<T>,
+{
+    let output = f(inputs);
+    jacobian_of(&output, inputs, false)
+}
+
+/// Hessian of the single-valued output of `f`, as blocks: block `[i][j]` is the (n_i, n_j)
+/// matrix of the second derivatives with respect to the values of `inputs[i]` and `inputs[j]`.
+#[allow(dead_code)]
+pub fn hessian<T, F>(f: F, inputs: &[TensorRef<T>]) -> Vec<Vec<TensorRef<T>>>
+where
+    T: Element,
+    F: FnOnce(&[TensorRef<T>]) -> TensorRef<T>,
+{
+    let output = f(inputs);
+    let options = BackwardOptions {
+        create_graph: true,
+        ..Default::default()
+    };
+    let grads = gradients(&output, None, inputs, options);
+
+    // The gradients share parts of their graphs, which only the last Jacobian can free.
+    grads
+        .iter()
+        .enumerate()
+        .map(|(i, grad)| jacobian_of(grad, inputs, i + 1 < grads.len()))
+        .collect()
+}
+
+/// Jacobian of `output` by one backward pass per output value, each seeded with a one-hot
+/// gradient. The graph is retained between the passes, and after the last one only when
+/// `retain_graph` is set.
+#[allow(dead_code)]
+fn jacobian_of<T: Element>(
+    output: &TensorRef<T>,
+    inputs: &[TensorRef<T>],
+    retain_graph: bool,
+) -> Vec<TensorRef<T>> {
+    let output_dim = output.borrow().arr.raw_dim();
+    let output_len = output.borrow().arr.len();
+
+    let mut rows: Vec<Vec<T>> = vec![Vec::with_capacity(output_len); inputs.len()];
+    for i in 0..output_len {
+        let mut seed: ArrayD<T> = ArrayD::zeros(output_dim.clone());
+        if let Some(v) = seed.iter_mut().nth(i) {
+            *v = T::one();
+        }
+
+        let options = BackwardOptions {
+            retain_graph: retain_graph || i + 1 < output_len,
+            ..Default::default()
+        };
+        let grads = gradients(output, Some(tensor!(seed)), inputs, options);
+        for (row, grad) in rows.iter_mut().zip(grads) {
+            row.extend(grad.borrow().arr.iter().copied());
+        }
+    }
+
+    inputs
+        .iter()
+        .zip(rows)
+        .map(|(input, row)| {
+            let input_len = input.borrow().arr.len();
+            let jacobian = Array2::from_shape_vec((output_len, input_len), row)
+                .expect("each row holds one value per input value");
+            tensor!(jacobian)
+        })
+        .collect()
+}
+
+/// Back-propagates from `output` in an isolated pass and returns what each of `inputs`
+/// received.
+fn gradients<T: Element>(
+    output: &TensorRef<T>,
+    seed: Option<TensorRef<T>>,
+    inputs: &[TensorRef<T>],
+    options: BackwardOptions,
+) -> Vec<TensorRef<T>> {
+    let grads = output
+        .try_grads_of(seed, inputs, options)
+        .unwrap_or_else(|err| panic!("{err}"));
+
+    inputs
+        .iter()
+        .zip(grads)
+        .map(|(input, grad)| {
+            grad.unwrap_or_else(|| tensor!(ArrayD::<T>::zeros(input.borrow().arr.raw_dim())))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use ndarray::array;
+
+    use crate::{matmul, prod, square, sum, tensor};
+
+    use super::{grad, hessian, jacobian, value_and_grad};
+
+    #[test]
+    fn grad_leaves_accumulated_gradients_alone() {
+        let x = tensor!(3.0);
+        let y = tensor!(2.0);
+        prod!(x, 10.0).backward(None);
+
+        let (value, grads) = value_and_grad(|v| prod!(prod!(v[0], v[0]), v[1]), &[x.clone(), y]);
+
+        assert_eq!(value.borrow().arr[[0, 0]], 18.0);
+        assert_eq!(grads[0].borrow().arr[[0, 0]], 12.0);
+        assert_eq!(grads[1].borrow().arr[[0, 0]], 9.0);
+        assert_eq!(x.borrow().grad().unwrap().arr[[0, 0]], 10.0);
+    }
+
+    #[test]
+    fn grad_leaves_captured_and_intermediate_tensors_alone() {
+        let w = tensor!(2.0);
+        let x = tensor!(3.0);
+        let hidden = RefCell::new(None);
+
+        let grads = grad(
+            |v| {
+                let h = prod!(w, v[0]);
+                *hidden.borrow_mut() = Some(h.clone());
+                square!(h)
+            },
+            &[x],
+        );
+
+        // d/dx (wx)^2 = 2w^2 x
+        assert_eq!(grads[0].borrow().arr[[0, 0]], 24.0);
+        assert!(w.borrow().grad.is_none());
+        let hidden = hidden.into_inner().unwrap();
+        assert!(hidden.borrow().grad.is_none());
+        assert!(hidden.borrow().is_graph_freed());
+    }
+
+    #[test]
+    fn grad_is_zero_for_unused_inputs() {
+        let x = tensor!(3.0);
+        let unused = tensor!(array![1.0, 2.0]);
+
+        let grads = grad(|v| square!(v[0]), &[x, unused]);
+
+        assert_eq!(grads[1].borrow().arr, array![0.0, 0.0].into_dyn());
+    }
+
+    #[test]
+    fn jacobian_of_matrix_product() {
+        let w = tensor!(array![[1.0, 2.0], [3.0, 4.0], [5.0, 6.0]]);
+        let x = tensor!(array![[1.0], [-1.0]]);
+
+        let jacobians = jacobian(|v| matmul!(v[0], v[1]), &[w, x]);
+
+        // d(Wx)_i / dx_j = W_ij
+        assert_eq!(
+            jacobians[1].borrow().arr,
+            array![[1.0, 2.0], [3.0, 4.0], [5.0, 6.0]].into_dyn()
+        );
+        assert_eq!(jacobians[0].borrow().arr.shape(), &[3, 6]);
+        let first_row: Vec<f64> = jacobians[0].borrow().arr.iter().take(6).copied().collect();
+        assert_eq!(first_row, vec![1.0, -1.0, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn hessian_blocks() {
+        let x = tensor!(array![1.0, 2.0]);
+        let y = tensor!(3.0);
+
+        // f = y * (x0^2 + x1^2)
+        let blocks = hessian(|v| prod!(sum!(square!(v[0])), v[1]), &[x, y]);
+
+        assert_eq!(
+            blocks[0][0].borrow().arr,
+            array![[6.0, 0.0], [0.0, 6.0]].into_dyn()
+        );
+        assert_eq!(blocks[0][1].borrow().arr, array![[2.0], [4.0]].into_dyn());
+        assert_eq!(blocks[1][0].borrow().arr, array![[2.0, 4.0]].into_dyn());
+        assert_eq!(blocks[1][1].borrow().arr, array![[0.0]].into_dyn());
+    }
+}
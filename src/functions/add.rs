@@ -1,54 +1,95 @@
-use std::{cell::RefCell, rc::Rc};
-
 use crate::{
-    name_manager::{NameManager, NAME_MANAGER},
-    operation::Operation,
-    tensor,
+    element::Element,
+    error::TensorError,
+    functions::Elementwise,
+    name_manager::new_name,
+    operation::{tangents_or_zeros, GradContext, Operation},
+    shape::check_broadcastable,
+    sum_to, tensor,
     tensor::{TensorBuilder, TensorRef},
+    vmap,
 };
 
 #[macro_export]
 macro_rules! add {
     ($val1:expr, $val2:expr) => {{
-        use crate::functions::Add;
-        use crate::operation::Operation;
-        use crate::tensor;
+        use $crate::functions::Add;
+        use $crate::tensor;
 
         let t1 = tensor!($val1.clone());
         let t2 = tensor!($val2.clone());
 
         let add = Add::new();
-        add.apply(&[t1, t2])
+        $crate::operation::Operation::apply(&add, &[t1, t2])
     }};
 }
 
 #[derive(Debug, Clone)]
-pub struct Add {
-    name_manager: Rc<RefCell<NameManager>>,
-}
+pub struct Add;
 
 impl Add {
     pub fn new() -> Self {
-        Add {
-            name_manager: NAME_MANAGER.with(|mn| mn.clone()),
-        }
+        Add
     }
 }
 
-impl Operation for Add {
-    fn apply(&self, inputs: &[TensorRef]) -> TensorRef {
+impl<T: Element> Operation<T> for Add {
+    fn name(&self) -> &str {
+        "add"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
         let a = &inputs[0];
         let b = &inputs[1];
-        let add = &a.borrow().arr + &b.borrow().arr;
-        let op_name = self.name_manager.clone().borrow_mut().new_name("add");
+        check_broadcastable(
+            "add",
+            a.try_borrow()?.arr.shape(),
+            b.try_borrow()?.arr.shape(),
+        )?;
+
+        let add = &a.try_borrow()?.arr + &b.try_borrow()?.arr;
+        let op_name = new_name("add");
+
+        Ok(
+            tensor!(add, name: &op_name, parents: vec![a.clone(), b.clone()], operation: Box::new(self.clone())),
+        )
+    }
 
-        tensor!(add, name: &op_name, parents: vec![a.clone(), b.clone()], operation: Box::new(self.clone()))
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let a_shape = args[0].borrow().arr.shape().to_vec();
+        let b_shape = args[1].borrow().arr.shape().to_vec();
+
+        vec![sum_to!(back_grad, &a_shape), sum_to!(back_grad, &b_shape)]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents_or_zeros(tangents, args)?;
+
+        Some(add!(t[0], t[1]))
     }
 
-    fn grad(&self, back_grad: TensorRef, _args: &[TensorRef]) -> Vec<TensorRef> {
-        let back_grad_arr = back_grad.borrow().arr.clone();
-        let grad = tensor!(back_grad_arr, name: "add_grad");
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        vmap::apply_elementwise(self, inputs, batched)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some("add".to_string())
+    }
 
-        vec![grad.clone(), grad]
+    fn elementwise(&self) -> Option<Elementwise> {
+        Some(Elementwise::Add)
     }
 }
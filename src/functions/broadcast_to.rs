@@ -0,0 +1,112 @@
+use crate::{
+    element::Element,
+    error::TensorError,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    reshape, sum_to, tensor,
+    tensor::{TensorBuilder, TensorRef},
+    vmap,
+};
+
+#[macro_export]
+macro_rules! broadcast_to {
+    ($val1:expr, $shape:expr) => {{
+        use $crate::functions::BroadcastTo;
+        use $crate::tensor;
+
+        let t = tensor!($val1.clone());
+
+        let broadcast_to = BroadcastTo::new($shape);
+        $crate::operation::Operation::apply(&broadcast_to, &[t])
+    }};
+}
+
+/// Repeats a value along its size-1 and missing leading axes until it has `shape`.
+#[derive(Debug, Clone)]
+pub struct BroadcastTo {
+    shape: Vec<usize>,
+}
+
+impl BroadcastTo {
+    pub fn new(shape: &[usize]) -> Self {
+        BroadcastTo {
+            shape: shape.to_vec(),
+        }
+    }
+}
+
+impl<T: Element> Operation<T> for BroadcastTo {
+    fn name(&self) -> &str {
+        "broadcast_to"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let a = &inputs[0];
+
+        let a_arr = &a.try_borrow()?.arr;
+        let broadcast = a_arr
+            .broadcast(self.shape.as_slice())
+            .ok_or_else(|| TensorError::ShapeMismatch {
+                op: "broadcast_to".to_string(),
+                lhs: a_arr.shape().to_vec(),
+                rhs: self.shape.clone(),
+            })?
+            .to_owned();
+        let op_name = new_name("broadcast_to");
+
+        Ok(
+            tensor!(broadcast, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let input_shape = args[0].borrow().arr.shape().to_vec();
+
+        vec![sum_to!(back_grad, &input_shape)]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        _args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
+
+        Some(broadcast_to!(t, &self.shape))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        let a = &inputs[0];
+        let batch_size = vmap::batch_size(inputs, batched);
+        let example_shape = vmap::example_shape(a, true);
+        if example_shape.len() > self.shape.len() {
+            return Err(TensorError::ShapeMismatch {
+                op: "broadcast_to".to_string(),
+                lhs: example_shape,
+                rhs: self.shape.clone(),
+            });
+        }
+
+        // Pads each example to the target rank before broadcasting it.
+        let mut padded_shape = vec![batch_size];
+        padded_shape.resize(1 + self.shape.len() - example_shape.len(), 1);
+        padded_shape.extend(&example_shape);
+        let mut shape = vec![batch_size];
+        shape.extend(&self.shape);
+
+        BroadcastTo::new(&shape).try_apply(&[reshape!(a, &padded_shape)])
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some(format!("broadcast_to {:?}", self.shape))
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{
+    element::Element,
+    error::TensorError,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    tensor,
+    tensor::{GraphNode, PendingGrads, TensorBuilder, TensorRef},
+    vmap,
+};
+
+/// Converts a tensor to another element type, e.g. `cast!(x, f32)`.
+#[macro_export]
+macro_rules! cast {
+    ($val1:expr, $ty:ty) => {{
+        use $crate::functions::Cast;
+        use $crate::tensor;
+
+        let t = tensor!($val1.clone());
+
+        let cast = Cast::new(t);
+        $crate::operation::Operation::<$ty>::apply(&cast, &[])
+    }};
+}
+
+/// Element type conversion from `S` to the output type.
+///
+/// The parents of a tensor all have its element type, so the source can't be one of them. The
+/// operation keeps it instead as a foreign parent and takes no inputs: a backward pass orders
+/// the source graph along with the rest, and the gradient of the result is converted back and
+/// added to the source's in the same pass.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct Cast<S: Element> {
+    source: TensorRef<S>,
+}
+
+impl<S: Element> Cast<S> {
+    #[allow(dead_code)]
+    pub fn new(source: TensorRef<S>) -> Self {
+        Cast { source }
+    }
+}
+
+impl<S: Element, T: Element> Operation<T> for Cast<S> {
+    fn name(&self) -> &str {
+        "cast"
+    }
+
+    fn try_apply(&self, _inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let cast = self.source.try_borrow()?.arr.mapv(T::convert);
+        let op_name = new_name("cast");
+
+        let cast = tensor!(cast, name: &op_name, operation: Box::new(self.clone()));
+        if vmap::is_batched(&self.source) {
+            vmap::mark_batched(&cast);
+        }
+
+        Ok(cast)
+    }
+
+    fn grad(
+        &self,
+        _back_grad: TensorRef<T>,
+        _args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        vec![]
+    }
+
+    fn foreign_parents(&self) -> Vec<Box<dyn GraphNode>> {
+        vec![Box::new(self.source.clone())]
+    }
+
+    fn foreign_grad(&self, back_grad: &TensorRef<T>, pending: &mut PendingGrads) {
+        pending.add(&self.source, cast!(back_grad, S));
+    }
+
+    fn jvp(
+        &self,
+        _tangents: &[Option<TensorRef<T>>],
+        _args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let source_tangent = self.source.borrow().tangent.clone()?;
+
+        Some(cast!(source_tangent, T))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        // Without inputs this isn't reached: `try_apply` carries the batch over from the source.
+        self.try_apply(inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        // Casts of different sources holding the same values are still different nodes.
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{add, error::TensorError, prod, tensor, tensor::TensorRef};
+
+    fn grad_of(t: &TensorRef) -> f64 {
+        t.borrow().grad().expect("tensor has no gradient").arr[[0, 0]]
+    }
+
+    #[test]
+    fn cast_is_back_propagated_in_the_same_pass() {
+        for cast_first in [true, false] {
+            let x = tensor!(3.0);
+            let y = prod!(x, x);
+            let round_trip = cast!(cast!(y, f32), f64);
+            let z = if cast_first {
+                add!(round_trip, y)
+            } else {
+                add!(y, round_trip)
+            };
+
+            z.backward(None);
+
+            // d/dx 2x^2 = 4x
+            assert_eq!(grad_of(&x), 12.0);
+            assert!(y.borrow().is_graph_freed());
+        }
+    }
+
+    #[test]
+    fn errors_beyond_a_cast_are_returned() {
+        let x = tensor!(3.0_f32);
+        let y = cast!(prod!(x, x), f64);
+
+        let _x_borrow = x.borrow_mut();
+        assert_eq!(y.try_backward(None), Err(TensorError::BorrowConflict));
+    }
+}
@@ -0,0 +1,144 @@
+use ndarray::{ArrayViewD, Axis};
+
+use crate::{
+    element::Element,
+    error::TensorError,
+    name_manager::new_name,
+    operation::{tangents_or_zeros, GradContext, Operation},
+    shape::check_axis,
+    slice, tensor,
+    tensor::{TensorBuilder, TensorRef},
+    vmap,
+};
+
+/// Joins tensors end to end along an existing axis: `concatenate!(0, a, b, c)`. They must have
+/// the same shape along the other axes.
+#[macro_export]
+macro_rules! concatenate {
+    ($axis:expr, $($val:expr),+ $(,)?) => {{
+        use $crate::functions::Concatenate;
+        use $crate::tensor;
+
+        let inputs = [$(tensor!($val.clone())),+];
+
+        let concatenate = Concatenate::new($axis);
+        $crate::operation::Operation::apply(&concatenate, &inputs)
+    }};
+}
+
+/// Joins its inputs along `axis`, the reverse of `Split`.
+#[derive(Debug, Clone)]
+pub struct Concatenate {
+    axis: usize,
+}
+
+impl Concatenate {
+    pub fn new(axis: usize) -> Self {
+        Concatenate { axis }
+    }
+}
+
+impl<T: Element> Operation<T> for Concatenate {
+    fn name(&self) -> &str {
+        "concatenate"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let guards = inputs
+            .iter()
+            .map(TensorRef::try_borrow)
+            .collect::<Result<Vec<_>, _>>()?;
+        let views: Vec<ArrayViewD<T>> = guards.iter().map(|guard| guard.arr.view()).collect();
+        let first_shape = views.first().map(|view| view.shape()).unwrap_or_default();
+        check_axis("concatenate", first_shape, self.axis)?;
+
+        let concatenated = ndarray::concatenate(Axis(self.axis), &views).map_err(|_| {
+            let other = views.iter().find(|view| {
+                view.ndim() != first_shape.len()
+                    || (0..view.ndim()).any(|d| d != self.axis && view.shape()[d] != first_shape[d])
+            });
+            TensorError::ShapeMismatch {
+                op: "concatenate".to_string(),
+                lhs: first_shape.to_vec(),
+                rhs: other.map(|view| view.shape().to_vec()).unwrap_or_default(),
+            }
+        })?;
+        drop(guards);
+        let op_name = new_name("concatenate");
+
+        Ok(
+            tensor!(concatenated, name: &op_name, parents: inputs.to_vec(), operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let mut start = 0;
+
+        args.iter()
+            .map(|arg| {
+                let end = start + arg.borrow().arr.shape()[self.axis];
+                let grad = slice!(back_grad, self.axis, start..end);
+                start = end;
+                grad
+            })
+            .collect()
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let tangents = tangents_or_zeros(tangents, args)?;
+
+        Some(self.apply(&tangents))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        let inputs = vmap::with_batch_axis(inputs, batched);
+
+        Concatenate::new(self.axis + 1).try_apply(&inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some(format!("concatenate {}", self.axis))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use crate::{prod, sum, tensor};
+
+    #[test]
+    fn inputs_are_joined_end_to_end() {
+        let a = tensor!(array![[1.0], [4.0]]);
+        let b = tensor!(array![[2.0, 3.0], [5.0, 6.0]]);
+
+        let joined = concatenate!(1, b, a);
+        assert_eq!(
+            joined.borrow().arr,
+            array![[2.0, 3.0, 1.0], [5.0, 6.0, 4.0]].into_dyn()
+        );
+
+        sum!(prod!(joined, array![[1.0, 2.0, 3.0]])).backward(None);
+        assert_eq!(
+            a.borrow().grad().unwrap().arr,
+            array![[3.0], [3.0]].into_dyn()
+        );
+        assert_eq!(
+            b.borrow().grad().unwrap().arr,
+            array![[1.0, 2.0], [1.0, 2.0]].into_dyn()
+        );
+    }
+}
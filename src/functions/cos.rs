@@ -1,52 +1,88 @@
-use std::{cell::RefCell, rc::Rc};
+use ndarray::arr0;
 
 use crate::{
-    name_manager::{NameManager, NAME_MANAGER},
-    operation::Operation,
-    tensor,
+    element::Element,
+    error::TensorError,
+    functions::Elementwise,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    prod, sin, tensor,
     tensor::{TensorBuilder, TensorRef},
 };
 
 #[macro_export]
 macro_rules! cos {
     ($val1:expr) => {{
-        use crate::functions::Cos;
-        use crate::operation::Operation;
+        use $crate::functions::Cos;
 
         let t = tensor!($val1.clone());
 
         let cos = Cos::new();
-        cos.apply(&[t])
+        $crate::operation::Operation::apply(&cos, &[t])
     }};
 }
 
 #[derive(Debug, Clone)]
-pub struct Cos {
-    name_manager: Rc<RefCell<NameManager>>,
-}
+pub struct Cos;
 
 impl Cos {
     pub fn new() -> Self {
-        Cos {
-            name_manager: NAME_MANAGER.with(|mn| mn.clone()),
-        }
+        Cos
     }
 }
 
-impl Operation for Cos {
-    fn apply(&self, inputs: &[TensorRef]) -> TensorRef {
+impl<T: Element> Operation<T> for Cos {
+    fn name(&self) -> &str {
+        "cos"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
         let a = &inputs[0];
-        let cos_arr = a.borrow().arr.cos();
-        let op_name = self.name_manager.clone().borrow_mut().new_name("cos");
+        let cos_arr = a.try_borrow()?.arr.cos();
+        let op_name = new_name("cos");
 
-        tensor!(cos_arr, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone()))
+        Ok(
+            tensor!(cos_arr, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone())),
+        )
     }
 
-    fn grad(&self, back_grad: TensorRef, args: &[TensorRef]) -> Vec<TensorRef> {
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
         let a = &args[0];
-        let grad_arr = back_grad.borrow().arr.clone() * -a.borrow().arr.sin();
-        let grad = tensor!(grad_arr, name: "cos_grad");
+        let minus_sin = prod!(sin!(a), arr0(T::cast_from(-1.0)));
+
+        vec![prod!(back_grad, minus_sin)]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
+        let minus_sin = prod!(sin!(args[0]), arr0(T::cast_from(-1.0)));
+
+        Some(prod!(t, minus_sin))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        // Elementwise, so the batch axis needs no special treatment.
+        self.try_apply(inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some("cos".to_string())
+    }
 
-        vec![grad]
+    fn elementwise(&self) -> Option<Elementwise> {
+        Some(Elementwise::Cos)
     }
 }
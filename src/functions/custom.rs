@@ -0,0 +1,323 @@
+use std::{fmt, sync::Arc};
+
+use ndarray::{ArrayD, ArrayViewD};
+
+use crate::{
+    element::Element,
+    error::TensorError,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    tensor,
+    tensor::{MaybeSync, TensorBuilder, TensorRef},
+};
+
+/// Applies a `CustomOp` to one or more tensors: `custom!(cube, x)`.
+#[macro_export]
+macro_rules! custom {
+    ($op:expr, $($val:expr),+ $(,)?) => {{
+        use $crate::tensor;
+
+        let inputs = [$(tensor!($val.clone())),+];
+        $crate::operation::Operation::apply(&$op, &inputs)
+    }};
+}
+
+/// Forward computation of a `CustomOp`, on the arrays of its inputs.
+#[allow(dead_code)]
+pub trait ForwardFn<T: Element>: Fn(&[ArrayViewD<T>]) -> ArrayD<T> + MaybeSync {}
+
+impl<T: Element, F> ForwardFn<T> for F where F: Fn(&[ArrayViewD<T>]) -> ArrayD<T> + MaybeSync {}
+
+/// Gradient rule of a `CustomOp`, with the signature of `Operation::grad`.
+#[allow(dead_code)]
+pub trait BackwardFn<T: Element>:
+    Fn(TensorRef<T>, &[TensorRef<T>], &GradContext<T>) -> Vec<TensorRef<T>> + MaybeSync
+{
+}
+
+impl<T: Element, F> BackwardFn<T> for F where
+    F: Fn(TensorRef<T>, &[TensorRef<T>], &GradContext<T>) -> Vec<TensorRef<T>> + MaybeSync
+{
+}
+
+/// Tangent rule of a `CustomOp`, with the signature of `Operation::jvp`.
+#[allow(dead_code)]
+pub trait JvpFn<T: Element>:
+    Fn(&[Option<TensorRef<T>>], &[TensorRef<T>]) -> Option<TensorRef<T>> + MaybeSync
+{
+}
+
+impl<T: Element, F> JvpFn<T> for F where
+    F: Fn(&[Option<TensorRef<T>>], &[TensorRef<T>]) -> Option<TensorRef<T>> + MaybeSync
+{
+}
+
+/// Batching rule of a `CustomOp`, with the signature of `Operation::apply_batched`.
+#[allow(dead_code)]
+pub trait BatchedFn<T: Element>:
+    Fn(&[TensorRef<T>], &[bool]) -> Result<TensorRef<T>, TensorError> + MaybeSync
+{
+}
+
+impl<T: Element, F> BatchedFn<T> for F where
+    F: Fn(&[TensorRef<T>], &[bool]) -> Result<TensorRef<T>, TensorError> + MaybeSync
+{
+}
+
+/// Differentiable operation defined by closures instead of a dedicated struct:
+///
+/// ```ignore
+/// let cube = CustomOp::new("cube", |x| x[0].mapv(|v| v * v * v))
+///     .backward(|back_grad, args, _ctx| vec![prod!(back_grad, prod!(square!(args[0]), 3.0))]);
+/// let y = custom!(cube, x);
+/// ```
+///
+/// The `backward` rule can reuse the forward result through `GradContext::output`.
+/// Each rule is only needed where it's used: back-propagating to inputs that require a gradient
+/// without a `backward` rule or computing tangents without a `jvp` rule panics, naming the op,
+/// and running under `vmap` without a `batched` rule fails with `TensorError::MissingRule`.
+/// The `backward` rule returns one gradient per input.
+#[allow(dead_code)]
+pub struct CustomOp<T: Element = f64> {
+    name: String,
+    forward: Arc<dyn ForwardFn<T>>,
+    backward: Option<Arc<dyn BackwardFn<T>>>,
+    jvp: Option<Arc<dyn JvpFn<T>>>,
+    batched: Option<Arc<dyn BatchedFn<T>>>,
+}
+
+#[allow(dead_code)]
+impl<T: Element> CustomOp<T> {
+    pub fn new(name: &str, forward: impl ForwardFn<T> + 'static) -> Self {
+        CustomOp {
+            name: name.to_string(),
+            forward: Arc::new(forward),
+            backward: None,
+            jvp: None,
+            batched: None,
+        }
+    }
+
+    pub fn backward(mut self, backward: impl BackwardFn<T> + 'static) -> Self {
+        self.backward = Some(Arc::new(backward));
+        self
+    }
+
+    pub fn jvp(mut self, jvp: impl JvpFn<T> + 'static) -> Self {
+        self.jvp = Some(Arc::new(jvp));
+        self
+    }
+
+    pub fn batched(mut self, batched: impl BatchedFn<T> + 'static) -> Self {
+        self.batched = Some(Arc::new(batched));
+        self
+    }
+}
+
+impl<T: Element> Clone for CustomOp<T> {
+    fn clone(&self) -> Self {
+        CustomOp {
+            name: self.name.clone(),
+            forward: self.forward.clone(),
+            backward: self.backward.clone(),
+            jvp: self.jvp.clone(),
+            batched: self.batched.clone(),
+        }
+    }
+}
+
+impl<T: Element> fmt::Debug for CustomOp<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomOp")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Element> Operation<T> for CustomOp<T> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let guards = inputs
+            .iter()
+            .map(TensorRef::try_borrow)
+            .collect::<Result<Vec<_>, _>>()?;
+        let arrays: Vec<ArrayViewD<T>> = guards.iter().map(|guard| guard.arr.view()).collect();
+
+        let output = (self.forward)(&arrays);
+        drop(guards);
+        let op_name = new_name(&self.name);
+
+        Ok(
+            tensor!(output, name: &op_name, parents: inputs.to_vec(), operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        if args.iter().all(|arg| !arg.borrow().requires_grad) {
+            return vec![];
+        }
+
+        let Some(backward) = &self.backward else {
+            panic!("custom op `{}` has no backward rule", self.name);
+        };
+        let grads = backward(back_grad, args, ctx);
+        assert_eq!(
+            grads.len(),
+            args.len(),
+            "custom op `{}`: backward rule has to return one gradient per input, got {} for {}",
+            self.name,
+            grads.len(),
+            args.len()
+        );
+
+        grads
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        if tangents.iter().all(Option::is_none) {
+            return None;
+        }
+
+        match &self.jvp {
+            Some(jvp) => jvp(tangents, args),
+            None => panic!("custom op `{}` has no jvp rule", self.name),
+        }
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        match &self.batched {
+            Some(rule) => rule(inputs, batched),
+            None => Err(TensorError::MissingRule {
+                op: self.name.clone(),
+                rule: "batching".to_string(),
+            }),
+        }
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        // The closures can't be compared.
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use crate::{
+        error::TensorError,
+        forward::jvp,
+        prod, square, sum, tensor,
+        tensor::TensorRef,
+        vmap::{self, vmap},
+    };
+
+    use super::CustomOp;
+
+    fn cube() -> CustomOp {
+        CustomOp::new("cube", |x| x[0].mapv(|v| v * v * v))
+            .backward(|back_grad, args, _ctx| vec![prod!(back_grad, prod!(square!(args[0]), 3.0))])
+            .jvp(|tangents, args| {
+                let t = tangents[0].as_ref()?;
+                Some(prod!(t, prod!(square!(args[0]), 3.0)))
+            })
+    }
+
+    #[test]
+    fn custom_op_is_differentiable() {
+        let cube = cube();
+        let x = tensor!(array![[1.0], [2.0]]);
+
+        let y = custom!(cube, x);
+        sum!(y).backward(None);
+
+        assert_eq!(y.borrow().arr, array![[1.0], [8.0]].into_dyn());
+        assert_eq!(
+            x.borrow().grad().unwrap().arr,
+            array![[3.0], [12.0]].into_dyn()
+        );
+
+        let (_, tangent) = jvp(
+            |x: &[TensorRef]| custom!(cube, x[0]),
+            &[x],
+            &[tensor!(array![[1.0], [1.0]])],
+        );
+        assert_eq!(tangent.borrow().arr, array![[3.0], [12.0]].into_dyn());
+    }
+
+    #[test]
+    fn custom_op_uses_its_batching_rule() {
+        // The rule runs with batching paused, so the op can be applied to the whole batch.
+        let cube = cube().batched(|inputs, _batched| Ok(custom!(cube(), inputs[0])));
+        let xs = tensor!(array![[1.0, 2.0], [3.0, 4.0]]);
+
+        let ys = vmap(|x| custom!(cube, x[0]), &[xs]);
+
+        assert_eq!(ys.borrow().arr, array![[1.0, 8.0], [27.0, 64.0]].into_dyn());
+    }
+
+    #[test]
+    #[should_panic(expected = "custom op `square` has no backward rule")]
+    fn missing_backward_rule_panics() {
+        let square = CustomOp::new("square", |x| x[0].mapv(|v| v * v));
+
+        sum!(custom!(square, tensor!(3.0))).backward(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "custom op `cube` has no batching rule")]
+    fn missing_batching_rule_panics() {
+        let cube = cube();
+
+        vmap(|x| custom!(cube, x[0]), &[tensor!(array![[1.0, 2.0]])]);
+    }
+
+    #[test]
+    fn missing_batching_rule_is_returned() {
+        let cube = cube();
+        let mut result = None;
+
+        vmap(
+            |x| {
+                result = Some(vmap::apply(&cube, x));
+                x[0].clone()
+            },
+            &[tensor!(array![[1.0, 2.0]])],
+        );
+
+        assert_eq!(
+            result.unwrap().unwrap_err(),
+            TensorError::MissingRule {
+                op: "cube".to_string(),
+                rule: "batching".to_string()
+            }
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "custom op `pair`: backward rule has to return one gradient per input, got 1 for 2"
+    )]
+    fn backward_rule_returning_too_few_gradients_panics() {
+        let pair = CustomOp::new("pair", |x| &x[0] + &x[1])
+            .backward(|back_grad, _args, _ctx| vec![back_grad]);
+
+        sum!(custom!(pair, tensor!(1.0), tensor!(2.0))).backward(None);
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{
+    element::Element,
+    error::TensorError,
+    functions::Elementwise,
+    name_manager::new_name,
+    operation::{tangents_or_zeros, GradContext, Operation},
+    prod,
+    shape::check_broadcastable,
+    square, sub, sum_to, tensor,
+    tensor::{TensorBuilder, TensorRef},
+    vmap,
+};
+
+#[macro_export]
+macro_rules! div {
+    ($val1:expr, $val2:expr) => {{
+        use $crate::functions::Div;
+        use $crate::tensor;
+
+        let t1 = tensor!($val1.clone());
+        let t2 = tensor!($val2.clone());
+
+        let div = Div::new();
+        $crate::operation::Operation::apply(&div, &[t1, t2])
+    }};
+}
+
+#[derive(Debug, Clone)]
+pub struct Div;
+
+impl Div {
+    pub fn new() -> Self {
+        Div
+    }
+}
+
+impl<T: Element> Operation<T> for Div {
+    fn name(&self) -> &str {
+        "div"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let a = &inputs[0];
+        let b = &inputs[1];
+        check_broadcastable(
+            "div",
+            a.try_borrow()?.arr.shape(),
+            b.try_borrow()?.arr.shape(),
+        )?;
+
+        let quotient = &a.try_borrow()?.arr / &b.try_borrow()?.arr;
+        let op_name = new_name("div");
+
+        Ok(
+            tensor!(quotient, name: &op_name, parents: vec![a.clone(), b.clone()], operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let a = &args[0];
+        let b = &args[1];
+        let a_shape = a.borrow().arr.shape().to_vec();
+        let b_shape = b.borrow().arr.shape().to_vec();
+
+        let grad_a = div!(back_grad, b);
+        let grad_b = prod!(div!(prod!(back_grad, a), square!(b)), T::cast_from(-1.0));
+
+        vec![sum_to!(grad_a, &a_shape), sum_to!(grad_b, &b_shape)]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents_or_zeros(tangents, args)?;
+        let a = &args[0];
+        let b = &args[1];
+
+        Some(div!(sub!(prod!(t[0], b), prod!(a, t[1])), square!(b)))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        vmap::apply_elementwise(self, inputs, batched)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some("div".to_string())
+    }
+
+    fn elementwise(&self) -> Option<Elementwise> {
+        Some(Elementwise::Div)
+    }
+}
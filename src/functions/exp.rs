@@ -1,53 +1,84 @@
-use std::{cell::RefCell, rc::Rc};
-
 use crate::{
-    name_manager::{NameManager, NAME_MANAGER},
-    operation::Operation,
-    tensor,
+    element::Element,
+    error::TensorError,
+    functions::Elementwise,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    prod, tensor,
     tensor::{TensorBuilder, TensorRef},
 };
 
 #[macro_export]
 macro_rules! exp {
     ($val1:expr) => {{
-        use crate::functions::Exp;
-        use crate::operation::Operation;
+        use $crate::functions::Exp;
 
         let t = tensor!($val1.clone());
 
         let exp = Exp::new();
-        exp.apply(&[t])
+        $crate::operation::Operation::apply(&exp, &[t])
     }};
 }
 
 #[derive(Debug, Clone)]
-pub struct Exp {
-    name_manager: Rc<RefCell<NameManager>>,
-}
+pub struct Exp;
 
 impl Exp {
     pub fn new() -> Self {
-        Exp {
-            name_manager: NAME_MANAGER.with(|mn| mn.clone()),
-        }
+        Exp
     }
 }
 
-impl Operation for Exp {
-    fn apply(&self, inputs: &[TensorRef]) -> TensorRef {
+impl<T: Element> Operation<T> for Exp {
+    fn name(&self) -> &str {
+        "exp"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
         let a = &inputs[0];
 
-        let exp = a.borrow().arr.mapv(|v| v.exp());
-        let op_name = self.name_manager.clone().borrow_mut().new_name("exp");
+        let exp = a.try_borrow()?.arr.mapv(|v| v.exp());
+        let op_name = new_name("exp");
+
+        Ok(
+            tensor!(exp, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        _args: &[TensorRef<T>],
+        ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        // The derivative of exp is its own output.
+        vec![prod!(back_grad, ctx.output())]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
 
-        tensor!(exp, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone()))
+        Some(prod!(t, exp!(args[0])))
     }
 
-    fn grad(&self, back_grad: TensorRef, args: &[TensorRef]) -> Vec<TensorRef> {
-        let a = &args[0];
-        let grad_arr = a.borrow().arr.exp();
-        let grad = tensor!(back_grad.borrow().arr.clone() * grad_arr, name: "exp_grad");
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        // Elementwise, so the batch axis needs no special treatment.
+        self.try_apply(inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some("exp".to_string())
+    }
 
-        vec![grad]
+    fn elementwise(&self) -> Option<Elementwise> {
+        Some(Elementwise::Exp)
     }
 }
@@ -0,0 +1,544 @@
+use ndarray::{arr0, ArrayD, IxDyn};
+
+use crate::{
+    add, cos, div,
+    element::Element,
+    error::TensorError,
+    exp,
+    grad_mode::is_grad_enabled,
+    ln,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    prod, relu,
+    shape::{broadcast_shape, unbroadcast},
+    sigmoid, sin, square, sub, sum_to, tanh, tensor,
+    tensor::{TensorBuilder, TensorRef},
+    vmap,
+};
+
+/// The elementwise operations `Fused` can combine, see `Operation::elementwise`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Elementwise {
+    Exp,
+    Ln,
+    Sin,
+    Cos,
+    Square,
+    Relu,
+    Sigmoid,
+    Tanh,
+    Add,
+    Sub,
+    Prod,
+    Div,
+}
+
+impl Elementwise {
+    pub fn name(self) -> &'static str {
+        match self {
+            Elementwise::Exp => "exp",
+            Elementwise::Ln => "ln",
+            Elementwise::Sin => "sin",
+            Elementwise::Cos => "cos",
+            Elementwise::Square => "square",
+            Elementwise::Relu => "relu",
+            Elementwise::Sigmoid => "sigmoid",
+            Elementwise::Tanh => "tanh",
+            Elementwise::Add => "add",
+            Elementwise::Sub => "sub",
+            Elementwise::Prod => "prod",
+            Elementwise::Div => "div",
+        }
+    }
+
+    pub fn arity(self) -> usize {
+        match self {
+            Elementwise::Add | Elementwise::Sub | Elementwise::Prod | Elementwise::Div => 2,
+            _ => 1,
+        }
+    }
+
+    fn scalar<T: Element>(self, args: &[T]) -> T {
+        let x = args[0];
+        match self {
+            Elementwise::Exp => x.exp(),
+            Elementwise::Ln => x.ln(),
+            Elementwise::Sin => x.sin(),
+            Elementwise::Cos => x.cos(),
+            Elementwise::Square => x * x,
+            Elementwise::Relu => x.max(T::zero()),
+            Elementwise::Sigmoid => T::one() / (T::one() + (-x).exp()),
+            Elementwise::Tanh => x.tanh(),
+            Elementwise::Add => x + args[1],
+            Elementwise::Sub => x - args[1],
+            Elementwise::Prod => x * args[1],
+            Elementwise::Div => x / args[1],
+        }
+    }
+
+    /// Derivative of the output `out` with respect to each of the scalars `args`, the scalar
+    /// counterpart of `partials`.
+    fn scalar_partials<T: Element>(self, args: &[T], out: T) -> [T; 2] {
+        let (x, zero, one) = (args[0], T::zero(), T::one());
+        match self {
+            Elementwise::Exp => [out, zero],
+            Elementwise::Ln => [one / x, zero],
+            Elementwise::Sin => [x.cos(), zero],
+            Elementwise::Cos => [-x.sin(), zero],
+            Elementwise::Square => [x + x, zero],
+            Elementwise::Relu if x > zero => [one, zero],
+            Elementwise::Relu => [zero, zero],
+            Elementwise::Sigmoid => [out * (one - out), zero],
+            Elementwise::Tanh => [one - out * out, zero],
+            Elementwise::Add => [one, one],
+            Elementwise::Sub => [one, -one],
+            Elementwise::Prod => [args[1], x],
+            Elementwise::Div => [one / args[1], -x / (args[1] * args[1])],
+        }
+    }
+
+    fn apply<T: Element>(self, args: &[TensorRef<T>]) -> TensorRef<T> {
+        let x = &args[0];
+        match self {
+            Elementwise::Exp => exp!(x),
+            Elementwise::Ln => ln!(x),
+            Elementwise::Sin => sin!(x),
+            Elementwise::Cos => cos!(x),
+            Elementwise::Square => square!(x),
+            Elementwise::Relu => relu!(x),
+            Elementwise::Sigmoid => sigmoid!(x),
+            Elementwise::Tanh => tanh!(x),
+            Elementwise::Add => add!(x, args[1]),
+            Elementwise::Sub => sub!(x, args[1]),
+            Elementwise::Prod => prod!(x, args[1]),
+            Elementwise::Div => div!(x, args[1]),
+        }
+    }
+
+    /// Derivative of the output `out` with respect to each of `args`, `None` where it is 1.
+    fn partials<T: Element>(
+        self,
+        args: &[TensorRef<T>],
+        out: &TensorRef<T>,
+    ) -> Vec<Option<TensorRef<T>>> {
+        let x = &args[0];
+        let one = || arr0(T::one());
+        let minus_one = || arr0(-T::one());
+        match self {
+            Elementwise::Exp => vec![Some(out.clone())],
+            Elementwise::Ln => vec![Some(div!(one(), x))],
+            Elementwise::Sin => vec![Some(cos!(x))],
+            Elementwise::Cos => vec![Some(prod!(sin!(x), minus_one()))],
+            Elementwise::Square => vec![Some(prod!(x, arr0(T::cast_from(2.0))))],
+            Elementwise::Relu => {
+                let mask = x
+                    .borrow()
+                    .arr
+                    .mapv(|v| if v > T::zero() { T::one() } else { T::zero() });
+                vec![Some(tensor!(mask, requires_grad: false))]
+            }
+            Elementwise::Sigmoid => vec![Some(prod!(out, sub!(one(), out)))],
+            Elementwise::Tanh => vec![Some(sub!(one(), square!(out)))],
+            Elementwise::Add => vec![None, None],
+            Elementwise::Sub => vec![None, Some(tensor!(minus_one(), requires_grad: false))],
+            Elementwise::Prod => vec![Some(args[1].clone()), Some(x.clone())],
+            Elementwise::Div => vec![
+                Some(div!(one(), args[1])),
+                Some(prod!(div!(x, square!(args[1])), minus_one())),
+            ],
+        }
+    }
+}
+
+/// Where a step of a `Fused` operation reads an operand from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// One of the inputs of the fused operation.
+    Input(usize),
+    /// The result of an earlier step.
+    Step(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub op: Elementwise,
+    pub operands: Vec<Operand>,
+}
+
+/// Chain of elementwise operations computed in a single pass over the values, without the
+/// intermediate tensors, e.g. `sigmoid(add(matmul, b))` once the matmul is computed. The result
+/// is the last step. Created by `Trace::fuse_elementwise`.
+///
+/// The gradient rule goes backward through all the steps at once, in a single pass over the
+/// values too. Only when a gradient graph is being created are the intermediate results
+/// recomputed with operations instead, so that the gradients can be differentiated again.
+#[derive(Debug, Clone)]
+pub struct Fused {
+    steps: Vec<Step>,
+}
+
+impl Fused {
+    pub fn new(steps: Vec<Step>) -> Self {
+        Fused { steps }
+    }
+
+    /// Broadcasts `inputs` to their common shape and calls `visit` on each element in row-major
+    /// order, with the values of the inputs and the results of every step there. Returns the
+    /// common shape.
+    fn sweep<T: Element>(
+        &self,
+        inputs: &[TensorRef<T>],
+        mut visit: impl FnMut(&[T], &[T]),
+    ) -> Result<Vec<usize>, TensorError> {
+        let arrays = inputs
+            .iter()
+            .map(TensorRef::try_borrow)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut shape: Vec<usize> = Vec::new();
+        for input in &arrays {
+            let input_shape = input.arr.shape();
+            shape =
+                broadcast_shape(&shape, input_shape).ok_or_else(|| TensorError::ShapeMismatch {
+                    op: "fused".to_string(),
+                    lhs: shape.clone(),
+                    rhs: input_shape.to_vec(),
+                })?;
+        }
+
+        let views = arrays
+            .iter()
+            .map(|input| input.arr.broadcast(IxDyn(&shape)))
+            .collect::<Option<Vec<_>>>()
+            .expect("inputs broadcast to their common shape");
+        let mut elements: Vec<_> = views.iter().map(|view| view.iter()).collect();
+
+        let len: usize = shape.iter().product();
+        let mut scalars = vec![T::zero(); inputs.len()];
+        let mut step_values = vec![T::zero(); self.steps.len()];
+        let mut operands: Vec<T> = Vec::with_capacity(2);
+        for _ in 0..len {
+            for (scalar, element) in scalars.iter_mut().zip(elements.iter_mut()) {
+                *scalar = *element
+                    .next()
+                    .expect("broadcast views have the same length");
+            }
+            for (j, step) in self.steps.iter().enumerate() {
+                Fused::scalar_operands(&step.operands, &scalars, &step_values, &mut operands);
+                step_values[j] = step.op.scalar(&operands);
+            }
+            visit(&scalars, &step_values);
+        }
+
+        Ok(shape)
+    }
+
+    fn scalar_operands<T: Element>(
+        operands: &[Operand],
+        scalars: &[T],
+        step_values: &[T],
+        out: &mut Vec<T>,
+    ) {
+        out.clear();
+        out.extend(operands.iter().map(|operand| match *operand {
+            Operand::Input(i) => scalars[i],
+            Operand::Step(k) => step_values[k],
+        }));
+    }
+
+    /// The gradient of each input computed without operations: each element of `back_grad` is
+    /// propagated backward through the steps right after the forward values at that element,
+    /// then the gradients are summed over the axes the inputs were broadcast along.
+    fn swept_input_grads<T: Element>(
+        &self,
+        back_grad: &TensorRef<T>,
+        args: &[TensorRef<T>],
+    ) -> Vec<TensorRef<T>> {
+        let back_grad = back_grad.borrow();
+        let mut back_values = back_grad.arr.iter();
+        let mut grads: Vec<Vec<T>> = vec![Vec::with_capacity(back_grad.arr.len()); args.len()];
+        let mut step_grads = vec![T::zero(); self.steps.len()];
+        let mut input_grads = vec![T::zero(); args.len()];
+        let mut operands: Vec<T> = Vec::with_capacity(2);
+
+        let shape = self
+            .sweep(args, |scalars, step_values| {
+                step_grads.fill(T::zero());
+                input_grads.fill(T::zero());
+                step_grads[self.steps.len() - 1] = *back_values
+                    .next()
+                    .expect("the gradient has the shape of the output");
+
+                for (j, step) in self.steps.iter().enumerate().rev() {
+                    Fused::scalar_operands(&step.operands, scalars, step_values, &mut operands);
+                    let partials = step.op.scalar_partials(&operands, step_values[j]);
+                    for (operand, partial) in step.operands.iter().zip(partials) {
+                        let contribution = step_grads[j] * partial;
+                        match *operand {
+                            Operand::Input(i) => input_grads[i] += contribution,
+                            Operand::Step(k) => step_grads[k] += contribution,
+                        }
+                    }
+                }
+
+                for (grad, &value) in grads.iter_mut().zip(&input_grads) {
+                    grad.push(value);
+                }
+            })
+            .unwrap_or_else(|err| panic!("{err}"));
+
+        grads
+            .into_iter()
+            .zip(args)
+            .map(|(grad, arg)| {
+                let grad = ArrayD::from_shape_vec(IxDyn(&shape), grad)
+                    .expect("one gradient value per element");
+                tensor!(unbroadcast(grad, arg.borrow().arr.shape()))
+            })
+            .collect()
+    }
+
+    /// The results of every step, computed with operations.
+    fn step_values<T: Element>(&self, args: &[TensorRef<T>]) -> Vec<TensorRef<T>> {
+        let mut values: Vec<TensorRef<T>> = Vec::with_capacity(self.steps.len());
+        for step in &self.steps {
+            let operands = Fused::operands(&step.operands, args, &values);
+            values.push(step.op.apply(&operands));
+        }
+        values
+    }
+
+    fn operands<T: Element>(
+        operands: &[Operand],
+        args: &[TensorRef<T>],
+        values: &[TensorRef<T>],
+    ) -> Vec<TensorRef<T>> {
+        operands
+            .iter()
+            .map(|operand| match *operand {
+                Operand::Input(i) => args[i].clone(),
+                Operand::Step(j) => values[j].clone(),
+            })
+            .collect()
+    }
+
+    /// Back-propagates `back_grad` from the last step to every step and input, returning the
+    /// gradient of each input, `None` for those that don't affect the result.
+    fn input_grads<T: Element>(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+    ) -> Vec<Option<TensorRef<T>>> {
+        let values = self.step_values(args);
+        let mut step_grads: Vec<Option<TensorRef<T>>> = vec![None; self.steps.len()];
+        let mut input_grads: Vec<Option<TensorRef<T>>> = vec![None; args.len()];
+        step_grads[self.steps.len() - 1] = Some(back_grad);
+
+        for (j, step) in self.steps.iter().enumerate().rev() {
+            let Some(grad) = step_grads[j].take() else {
+                continue;
+            };
+            let operands = Fused::operands(&step.operands, args, &values);
+            let partials = step.op.partials(&operands, &values[j]);
+
+            for ((operand, value), partial) in step.operands.iter().zip(&operands).zip(partials) {
+                let shape = value.borrow().arr.shape().to_vec();
+                let contribution = match partial {
+                    Some(partial) => prod!(grad, partial),
+                    None => grad.clone(),
+                };
+                let contribution = sum_to!(contribution, &shape);
+
+                let slot = match *operand {
+                    Operand::Input(i) => &mut input_grads[i],
+                    Operand::Step(k) => &mut step_grads[k],
+                };
+                *slot = Some(match slot.take() {
+                    Some(existing) => add!(existing, contribution),
+                    None => contribution,
+                });
+            }
+        }
+
+        input_grads
+    }
+}
+
+impl<T: Element> Operation<T> for Fused {
+    fn name(&self) -> &str {
+        "fused"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let mut values = Vec::new();
+        let shape = self.sweep(inputs, |_, step_values| {
+            values.push(step_values[self.steps.len() - 1]);
+        })?;
+
+        let fused = ArrayD::from_shape_vec(IxDyn(&shape), values).expect("one value per element");
+        let op_name = new_name("fused");
+
+        Ok(
+            tensor!(fused, name: &op_name, parents: inputs.to_vec(), operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        // Without a gradient graph to record, nothing needs the intermediate tensors.
+        if !is_grad_enabled() {
+            return self.swept_input_grads(&back_grad, args);
+        }
+
+        self.input_grads(back_grad, args)
+            .into_iter()
+            .zip(args)
+            .map(|(grad, arg)| {
+                grad.unwrap_or_else(|| {
+                    let zeros = ArrayD::zeros(arg.borrow().arr.raw_dim());
+                    tensor!(zeros, requires_grad: false)
+                })
+            })
+            .collect()
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        if tangents.iter().all(Option::is_none) {
+            return None;
+        }
+
+        let values = self.step_values(args);
+        let mut step_tangents: Vec<Option<TensorRef<T>>> = Vec::with_capacity(self.steps.len());
+        for (j, step) in self.steps.iter().enumerate() {
+            let operands = Fused::operands(&step.operands, args, &values);
+            let partials = step.op.partials(&operands, &values[j]);
+
+            let mut tangent: Option<TensorRef<T>> = None;
+            for (operand, partial) in step.operands.iter().zip(partials) {
+                let operand_tangent = match *operand {
+                    Operand::Input(i) => tangents[i].clone(),
+                    Operand::Step(k) => step_tangents[k].clone(),
+                };
+                let Some(operand_tangent) = operand_tangent else {
+                    continue;
+                };
+                let contribution = match partial {
+                    Some(partial) => prod!(operand_tangent, partial),
+                    None => operand_tangent,
+                };
+                tangent = Some(match tangent {
+                    Some(existing) => add!(existing, contribution),
+                    None => contribution,
+                });
+            }
+            step_tangents.push(tangent);
+        }
+
+        step_tangents.pop().flatten()
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        vmap::apply_elementwise(self, inputs, batched)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        let steps: Vec<String> = self
+            .steps
+            .iter()
+            .map(|step| {
+                let operands: Vec<String> = step
+                    .operands
+                    .iter()
+                    .map(|operand| match *operand {
+                        Operand::Input(i) => format!("input {i}"),
+                        Operand::Step(k) => format!("step {k}"),
+                    })
+                    .collect();
+                format!("{}({})", step.op.name(), operands.join(", "))
+            })
+            .collect();
+
+        Some(format!("fused {}", steps.join(" ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, ArcArray, IxDyn};
+
+    use crate::{add, functional::hessian, operation::Operation, prod, sigmoid, sum, tanh, tensor};
+
+    use super::{Elementwise, Fused, Operand, Step};
+
+    #[test]
+    fn fused_chain_matches_separate_operations() {
+        let z = tensor!(array![[0.5, -1.0], [2.0, 0.0]]);
+        let b = tensor!(array![[1.0], [-1.0]]);
+        let fused = Fused::new(vec![
+            Step {
+                op: Elementwise::Add,
+                operands: vec![Operand::Input(0), Operand::Input(1)],
+            },
+            Step {
+                op: Elementwise::Sigmoid,
+                operands: vec![Operand::Step(0)],
+            },
+        ]);
+
+        let y = fused.apply(&[z.clone(), b.clone()]);
+        sum!(y).backward(None);
+        let (z_grad, b_grad) = (
+            z.borrow().grad().unwrap().arr.clone(),
+            b.borrow().grad().unwrap().arr.clone(),
+        );
+
+        z.zero_grad();
+        b.zero_grad();
+        let expected = sigmoid!(add!(z, b));
+        sum!(expected).backward(None);
+
+        let close = |a: &ArcArray<f64, IxDyn>, e: &ArcArray<f64, IxDyn>| {
+            a.shape() == e.shape() && a.iter().zip(e).all(|(a, e)| (a - e).abs() < 1e-12)
+        };
+        assert!(close(&y.borrow().arr, &expected.borrow().arr));
+        assert!(close(&z_grad, &z.borrow().grad().unwrap().arr));
+        assert!(close(&b_grad, &b.borrow().grad().unwrap().arr));
+    }
+
+    #[test]
+    fn gradient_graph_goes_through_the_steps() {
+        let fused = Fused::new(vec![
+            Step {
+                op: Elementwise::Prod,
+                operands: vec![Operand::Input(0), Operand::Input(0)],
+            },
+            Step {
+                op: Elementwise::Tanh,
+                operands: vec![Operand::Step(0)],
+            },
+        ]);
+        let x = tensor!(array![0.3_f64, -0.7]);
+
+        let blocks = hessian(|v| sum!(fused.apply(v)), std::slice::from_ref(&x));
+        let expected = hessian(|v| sum!(tanh!(prod!(v[0], v[0]))), &[x]);
+
+        let (actual, expected) = (&blocks[0][0].borrow().arr, &expected[0][0].borrow().arr);
+        assert_eq!(actual.shape(), expected.shape());
+        assert!(actual
+            .iter()
+            .zip(expected)
+            .all(|(a, e)| (a - e).abs() < 1e-12));
+    }
+}
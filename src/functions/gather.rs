@@ -0,0 +1,145 @@
+use ndarray::ArrayD;
+
+use crate::{
+    element::Element,
+    error::TensorError,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    scatter,
+    shape::{check_index_shape, position},
+    tensor,
+    tensor::{TensorBuilder, TensorRef},
+    vmap,
+};
+
+/// Picks values of `x` along `axis` at the positions held by `index`: `gather!(x, 1, index)`.
+///
+/// The result has the shape of `index`. Its value at each position is the value of `x` at the
+/// same position, except along `axis` where the position is read from `index`. The index is
+/// stored as floats, like the indices returned by `top_k!`, and receives no gradient.
+#[macro_export]
+macro_rules! gather {
+    ($val1:expr, $axis:expr, $index:expr) => {{
+        use $crate::functions::Gather;
+        use $crate::tensor;
+
+        let t = tensor!($val1.clone());
+        let index = tensor!($index.clone());
+
+        let gather = Gather::new($axis);
+        $crate::operation::Operation::apply(&gather, &[t, index])
+    }};
+}
+
+/// Reads values at the positions of an index, the reverse of `Scatter`.
+#[derive(Debug, Clone)]
+pub struct Gather {
+    axis: usize,
+}
+
+impl Gather {
+    pub fn new(axis: usize) -> Self {
+        Gather { axis }
+    }
+}
+
+impl<T: Element> Operation<T> for Gather {
+    fn name(&self) -> &str {
+        "gather"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let a = &inputs[0];
+        let index = &inputs[1];
+        let (a_arr, index_arr) = (&a.try_borrow()?.arr, &index.try_borrow()?.arr);
+        check_index_shape("gather", a_arr.shape(), index_arr.shape(), self.axis)?;
+
+        let len = a_arr.shape()[self.axis];
+        let mut values = Vec::with_capacity(index_arr.len());
+        for (mut idx, &value) in index_arr.indexed_iter() {
+            idx[self.axis] = position("gather", value, len)?;
+            values.push(a_arr[&idx]);
+        }
+
+        let gather =
+            ArrayD::from_shape_vec(index_arr.raw_dim(), values).expect("one value per index");
+        let op_name = new_name("gather");
+
+        Ok(
+            tensor!(gather, name: &op_name, parents: vec![a.clone(), index.clone()], operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let zeros = ArrayD::zeros(args[0].borrow().arr.raw_dim());
+        let index_zeros = ArrayD::zeros(args[1].borrow().arr.raw_dim());
+
+        vec![
+            scatter!(
+                tensor!(zeros, requires_grad: false),
+                self.axis,
+                args[1],
+                back_grad
+            ),
+            tensor!(index_zeros, requires_grad: false),
+        ]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
+
+        Some(gather!(t, self.axis, args[1]))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        let inputs = vmap::with_batch_axis(inputs, batched);
+
+        Gather::new(self.axis + 1).try_apply(&inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some(format!("gather {}", self.axis))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use crate::{sum, tensor, vmap::vmap};
+
+    #[test]
+    fn gradient_is_scattered_back_to_the_gathered_positions() {
+        let x = tensor!(array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        let index = tensor!(array![[2.0, 2.0], [0.0, 1.0]]);
+
+        let y = gather!(x, 1, index);
+        sum!(y).backward(None);
+
+        assert_eq!(y.borrow().arr, array![[3.0, 3.0], [4.0, 5.0]].into_dyn());
+        assert_eq!(
+            x.borrow().grad().unwrap().arr,
+            array![[0.0, 0.0, 2.0], [1.0, 1.0, 0.0]].into_dyn()
+        );
+
+        // The index is shared by every example.
+        let ys = vmap(
+            |x| gather!(x[0], 1, index),
+            &[tensor!(array![[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]])],
+        );
+        assert_eq!(ys.borrow().arr, array![[[3.0, 3.0], [4.0, 5.0]]].into_dyn());
+    }
+}
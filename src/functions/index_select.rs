@@ -0,0 +1,130 @@
+use ndarray::{ArrayD, Axis};
+
+use crate::{
+    element::Element,
+    error::TensorError,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    scatter,
+    shape::check_axis,
+    tensor,
+    tensor::{TensorBuilder, TensorRef},
+};
+
+/// Picks the entries of `x` at `indices` along `axis`, in that order:
+/// `index_select!(x, 0, &[2, 0, 2])`. An entry can be picked several times.
+#[macro_export]
+macro_rules! index_select {
+    ($val1:expr, $axis:expr, $indices:expr) => {{
+        use $crate::functions::IndexSelect;
+        use $crate::tensor;
+
+        let t = tensor!($val1.clone());
+
+        let index_select = IndexSelect::new($axis, $indices);
+        $crate::operation::Operation::apply(&index_select, &[t])
+    }};
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexSelect {
+    axis: usize,
+    indices: Vec<usize>,
+}
+
+impl IndexSelect {
+    pub fn new(axis: usize, indices: &[usize]) -> Self {
+        IndexSelect {
+            axis,
+            indices: indices.to_vec(),
+        }
+    }
+
+    /// Adds `grad`, the gradient of entries picked along `axis` of a tensor of shape
+    /// `input_shape`, back into the entries they were picked from: entry `i` of `grad` along
+    /// `axis` was picked from entry `indices[i]`.
+    pub fn spread<T: Element>(
+        grad: &TensorRef<T>,
+        input_shape: &[usize],
+        axis: usize,
+        indices: &[usize],
+    ) -> TensorRef<T> {
+        let index = ArrayD::from_shape_fn(grad.borrow().arr.raw_dim(), |idx| {
+            T::cast_from(indices[idx[axis]] as f64)
+        });
+        let zeros = ArrayD::zeros(input_shape);
+
+        scatter!(
+            tensor!(zeros, requires_grad: false),
+            axis,
+            tensor!(index, requires_grad: false),
+            grad
+        )
+    }
+}
+
+impl<T: Element> Operation<T> for IndexSelect {
+    fn name(&self) -> &str {
+        "index_select"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let a = &inputs[0];
+        let a_arr = &a.try_borrow()?.arr;
+        check_axis("index_select", a_arr.shape(), self.axis)?;
+
+        let len = a_arr.shape()[self.axis];
+        if let Some(&index) = self.indices.iter().find(|&&index| index >= len) {
+            return Err(TensorError::IndexOutOfBounds {
+                op: "index_select".to_string(),
+                index: index as i64,
+                len,
+            });
+        }
+
+        let selected = a_arr.select(Axis(self.axis), &self.indices);
+        let op_name = new_name("index_select");
+
+        Ok(
+            tensor!(selected, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let input_shape = args[0].borrow().arr.shape().to_vec();
+
+        vec![IndexSelect::spread(
+            &back_grad,
+            &input_shape,
+            self.axis,
+            &self.indices,
+        )]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        _args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
+
+        Some(index_select!(t, self.axis, &self.indices))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        IndexSelect::new(self.axis + 1, &self.indices).try_apply(inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some(format!("index_select {} {:?}", self.axis, self.indices))
+    }
+}
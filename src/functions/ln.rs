@@ -1,8 +1,10 @@
-use std::{cell::RefCell, rc::Rc};
-
 use crate::{
-    name_manager::{NameManager, NAME_MANAGER},
-    operation::Operation,
+    div,
+    element::Element,
+    error::TensorError,
+    functions::Elementwise,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
     tensor,
     tensor::{TensorBuilder, TensorRef},
 };
@@ -10,45 +12,76 @@ use crate::{
 #[macro_export]
 macro_rules! ln {
     ($val1:expr) => {{
-        use crate::functions::Ln;
-        use crate::operation::Operation;
-        use crate::tensor;
+        use $crate::functions::Ln;
+        use $crate::tensor;
 
         let t = tensor!($val1.clone());
 
         let ln = Ln::new();
-        ln.apply(&[t])
+        $crate::operation::Operation::apply(&ln, &[t])
     }};
 }
 
 #[derive(Debug, Clone)]
-pub struct Ln {
-    name_manager: Rc<RefCell<NameManager>>,
-}
+pub struct Ln;
 
 impl Ln {
     pub fn new() -> Self {
-        Ln {
-            name_manager: NAME_MANAGER.with(|mn| mn.clone()),
-        }
+        Ln
     }
 }
 
-impl Operation for Ln {
-    fn apply(&self, inputs: &[TensorRef]) -> TensorRef {
+impl<T: Element> Operation<T> for Ln {
+    fn name(&self) -> &str {
+        "ln"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
         let a = &inputs[0];
 
-        let lns = a.borrow().arr.mapv(|v| v.ln());
-        let op_name = self.name_manager.clone().borrow_mut().new_name("ln");
+        let lns = a.try_borrow()?.arr.mapv(|v| v.ln());
+        let op_name = new_name("ln");
 
-        tensor!(lns, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone()))
+        Ok(
+            tensor!(lns, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone())),
+        )
     }
 
-    fn grad(&self, back_grad: TensorRef, args: &[TensorRef]) -> Vec<TensorRef> {
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
         let a = &args[0];
-        let grad_arr = a.borrow().arr.mapv(|x| 1.0 / x);
-        let grad = tensor!(back_grad.borrow().arr.clone() * grad_arr, name: "ln_grad");
 
-        vec![grad]
+        vec![div!(back_grad, a)]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
+
+        Some(div!(t, args[0]))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        // Elementwise, so the batch axis needs no special treatment.
+        self.try_apply(inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some("ln".to_string())
+    }
+
+    fn elementwise(&self) -> Option<Elementwise> {
+        Some(Elementwise::Ln)
     }
 }
@@ -0,0 +1,137 @@
+use ndarray::{ArcArray, Array1, ArrayD, Axis, IxDyn};
+
+use crate::{
+    element::Element,
+    error::TensorError,
+    functions::IndexSelect,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    reshape, tensor,
+    tensor::{TensorBuilder, TensorRef},
+    vmap,
+};
+
+/// The values of `x` where `mask` is non-zero, flattened in row-major order:
+/// `masked_select!(x, mask)`. The mask has the shape of `x` and receives no gradient.
+#[macro_export]
+macro_rules! masked_select {
+    ($val1:expr, $mask:expr) => {{
+        use $crate::functions::MaskedSelect;
+        use $crate::tensor;
+
+        let t = tensor!($val1.clone());
+        let mask = tensor!($mask.clone());
+
+        let masked_select = MaskedSelect::new();
+        $crate::operation::Operation::apply(&masked_select, &[t, mask])
+    }};
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct MaskedSelect;
+
+impl MaskedSelect {
+    #[allow(dead_code)]
+    pub fn new() -> Self {
+        MaskedSelect
+    }
+
+    /// Positions of the selected values in the flattened input.
+    fn positions<T: Element>(mask: &ArcArray<T, IxDyn>) -> Vec<usize> {
+        mask.iter()
+            .enumerate()
+            .filter(|(_, &m)| m != T::zero())
+            .map(|(position, _)| position)
+            .collect()
+    }
+}
+
+impl<T: Element> Operation<T> for MaskedSelect {
+    fn name(&self) -> &str {
+        "masked_select"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let a = &inputs[0];
+        let mask = &inputs[1];
+        let (a_arr, mask_arr) = (&a.try_borrow()?.arr, &mask.try_borrow()?.arr);
+        if a_arr.shape() != mask_arr.shape() {
+            return Err(TensorError::ShapeMismatch {
+                op: "masked_select".to_string(),
+                lhs: a_arr.shape().to_vec(),
+                rhs: mask_arr.shape().to_vec(),
+            });
+        }
+
+        let selected: Array1<T> = a_arr
+            .iter()
+            .zip(mask_arr)
+            .filter(|(_, &m)| m != T::zero())
+            .map(|(&x, _)| x)
+            .collect();
+        let op_name = new_name("masked_select");
+
+        Ok(
+            tensor!(selected, name: &op_name, parents: vec![a.clone(), mask.clone()], operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let input_shape = args[0].borrow().arr.shape().to_vec();
+        let mask = &args[1].borrow().arr;
+        let positions = MaskedSelect::positions(mask);
+        let flat = IndexSelect::spread(&back_grad, &[mask.len()], 0, &positions);
+
+        vec![
+            reshape!(flat, &input_shape),
+            tensor!(ArrayD::zeros(mask.raw_dim()), requires_grad: false),
+        ]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
+
+        Some(masked_select!(t, args[1]))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        let inputs = vmap::with_batch_axis(inputs, batched);
+
+        // The examples are stacked again, so they must all select as many values.
+        let counts: Vec<usize> = inputs[1]
+            .try_borrow()?
+            .arr
+            .axis_iter(Axis(0))
+            .map(|mask| mask.iter().filter(|&&m| m != T::zero()).count())
+            .collect();
+        let count = counts.first().copied().unwrap_or(0);
+        if let Some(&other) = counts.iter().find(|&&other| other != count) {
+            return Err(TensorError::ShapeMismatch {
+                op: "masked_select".to_string(),
+                lhs: vec![count],
+                rhs: vec![other],
+            });
+        }
+
+        let selected = self.try_apply(&inputs)?;
+        Ok(reshape!(selected, &[counts.len(), count]))
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some("masked_select".to_string())
+    }
+}
@@ -1,10 +1,14 @@
-use std::{cell::RefCell, rc::Rc};
+use ndarray::{ArcArray, ArrayView2, Ix2, IxDyn};
 
 use crate::{
-    name_manager::{NameManager, NAME_MANAGER},
-    operation::Operation,
-    tensor,
+    add,
+    element::Element,
+    error::TensorError,
+    name_manager::new_name,
+    operation::{tangents_or_zeros, GradContext, Operation},
+    permute, prod, reshape, sum_to, tensor,
     tensor::{TensorBuilder, TensorRef},
+    transpose, vmap,
 };
 
 #[macro_export]
@@ -18,51 +22,137 @@ macro_rules! matrix {
 #[macro_export]
 macro_rules! matmul {
     ($val1:expr, $val2:expr) => {{
-        use crate::functions::MatMul;
-        use crate::operation::Operation;
+        use $crate::functions::MatMul;
 
         let t1 = tensor!($val1.clone());
         let t2 = tensor!($val2.clone());
 
         let matmul = MatMul::new();
-        matmul.apply(&[t1, t2])
+        $crate::operation::Operation::apply(&matmul, &[t1, t2])
     }};
 }
 
 #[derive(Debug, Clone)]
-pub struct MatMul {
-    name_manager: Rc<RefCell<NameManager>>,
-}
+pub struct MatMul;
 
 impl MatMul {
     pub fn new() -> Self {
-        MatMul {
-            name_manager: NAME_MANAGER.with(|mn| mn.clone()),
-        }
+        MatMul
+    }
+
+    fn as_matrix<T: Element>(arr: &ArcArray<T, IxDyn>) -> Option<ArrayView2<'_, T>> {
+        arr.view().into_dimensionality::<Ix2>().ok()
     }
 }
 
-impl Operation for MatMul {
-    fn apply(&self, inputs: &[TensorRef]) -> TensorRef {
+impl<T: Element> Operation<T> for MatMul {
+    fn name(&self) -> &str {
+        "matmul"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
         let a = &inputs[0];
         let b = &inputs[1];
 
-        let mul = a.borrow().arr.clone().dot(&b.borrow().arr);
-        let op_name = self.name_manager.clone().borrow_mut().new_name("matmul");
+        let a_arr = &a.try_borrow()?.arr;
+        let b_arr = &b.try_borrow()?.arr;
+        let mismatch = || TensorError::ShapeMismatch {
+            op: "matmul".to_string(),
+            lhs: a_arr.shape().to_vec(),
+            rhs: b_arr.shape().to_vec(),
+        };
 
-        tensor!(mul, name: &op_name, parents: vec![a.clone(), b.clone()], operation: Box::new(self.clone()))
+        // Both operands have to be matrices, with as many columns in `a` as rows in `b`.
+        let (Some(a_mat), Some(b_mat)) = (Self::as_matrix(a_arr), Self::as_matrix(b_arr)) else {
+            return Err(mismatch());
+        };
+        if a_mat.ncols() != b_mat.nrows() {
+            return Err(mismatch());
+        }
+
+        let mul = a_mat.dot(&b_mat);
+        let op_name = new_name("matmul");
+
+        Ok(
+            tensor!(mul, name: &op_name, parents: vec![a.clone(), b.clone()], operation: Box::new(self.clone())),
+        )
     }
 
-    fn grad(&self, back_grad: TensorRef, args: &[TensorRef]) -> Vec<TensorRef> {
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
         let a = &args[0];
         let b = &args[1];
 
-        let a_grad = back_grad.borrow().arr.clone().dot(&b.borrow().arr.t());
-        let b_grad = a.borrow().arr.t().clone().dot(&back_grad.borrow().arr);
-
         vec![
-            tensor!(a_grad, name: "matmul_grad"),
-            tensor!(b_grad, name: "matmul_grad"),
+            matmul!(back_grad, transpose!(b)),
+            matmul!(transpose!(a), back_grad),
         ]
     }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents_or_zeros(tangents, args)?;
+
+        Some(add!(matmul!(t[0], args[1]), matmul!(args[0], t[1])))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        let a = &inputs[0];
+        let b = &inputs[1];
+        let a_shape = vmap::example_shape(a, batched[0]);
+        let b_shape = vmap::example_shape(b, batched[1]);
+        let batch_size = vmap::batch_size(inputs, batched);
+
+        let (&[m, k], &[k_b, n]) = (a_shape.as_slice(), b_shape.as_slice()) else {
+            return Err(TensorError::ShapeMismatch {
+                op: "matmul".to_string(),
+                lhs: a_shape,
+                rhs: b_shape,
+            });
+        };
+        if k != k_b {
+            return Err(TensorError::ShapeMismatch {
+                op: "matmul".to_string(),
+                lhs: a_shape,
+                rhs: b_shape,
+            });
+        }
+
+        match (batched[0], batched[1]) {
+            // The rows of all the examples of `a` stacked into one matrix.
+            (true, false) => {
+                let stacked = reshape!(a, &[batch_size * m, k]);
+                Ok(reshape!(matmul!(stacked, b), &[batch_size, m, n]))
+            }
+            // The columns of all the examples of `b` side by side in one matrix.
+            (false, true) => {
+                let side_by_side = reshape!(permute!(b, &[1, 0, 2]), &[k, batch_size * n]);
+                let product = reshape!(matmul!(a, side_by_side), &[m, batch_size, n]);
+                Ok(permute!(product, &[1, 0, 2]))
+            }
+            // Products of every pair of entries, summed over the shared axis.
+            _ => {
+                let a_entries = reshape!(a, &[batch_size, m, k, 1]);
+                let b_entries = reshape!(b, &[batch_size, 1, k, n]);
+                let products = prod!(a_entries, b_entries);
+                let sums = sum_to!(products, &[batch_size, m, 1, n]);
+                Ok(reshape!(sums, &[batch_size, m, n]))
+            }
+        }
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some("matmul".to_string())
+    }
 }
@@ -1,43 +1,100 @@
 mod add;
+mod broadcast_to;
+mod cast;
+mod concatenate;
 mod cos;
+mod custom;
+mod div;
 mod exp;
+mod fused;
+mod gather;
+mod index_select;
 mod ln;
+mod masked_select;
 mod matmul;
+mod permute;
 mod prod;
 mod relu;
+mod reshape;
+mod scatter;
 mod sigmoid;
 mod sin;
+mod slice;
 mod softmax;
+mod split;
 mod square;
+mod stack;
+mod stop_gradient;
 mod sub;
 mod sum;
+mod sum_to;
 mod tanh;
+mod top_k;
+mod transpose;
 
 #[allow(unused_imports)]
 pub use add::*;
 #[allow(unused_imports)]
+pub use broadcast_to::*;
+#[allow(unused_imports)]
+pub use cast::*;
+#[allow(unused_imports)]
+pub use concatenate::*;
+#[allow(unused_imports)]
 pub use cos::*;
 #[allow(unused_imports)]
+pub use custom::*;
+#[allow(unused_imports)]
+pub use div::*;
+#[allow(unused_imports)]
 pub use exp::*;
 #[allow(unused_imports)]
+pub use fused::*;
+#[allow(unused_imports)]
+pub use gather::*;
+#[allow(unused_imports)]
+pub use index_select::*;
+#[allow(unused_imports)]
 pub use ln::*;
 #[allow(unused_imports)]
+pub use masked_select::*;
+#[allow(unused_imports)]
 pub use matmul::*;
 #[allow(unused_imports)]
+pub use permute::*;
+#[allow(unused_imports)]
 pub use prod::*;
 #[allow(unused_imports)]
 pub use relu::*;
 #[allow(unused_imports)]
+pub use reshape::*;
+#[allow(unused_imports)]
+pub use scatter::*;
+#[allow(unused_imports)]
 pub use sigmoid::*;
 #[allow(unused_imports)]
 pub use sin::*;
 #[allow(unused_imports)]
+pub use slice::*;
+#[allow(unused_imports)]
 pub use softmax::*;
 #[allow(unused_imports)]
+pub use split::*;
+#[allow(unused_imports)]
 pub use square::*;
 #[allow(unused_imports)]
+pub use stack::*;
+#[allow(unused_imports)]
+pub use stop_gradient::*;
+#[allow(unused_imports)]
 pub use sub::*;
 #[allow(unused_imports)]
 pub use sum::*;
 #[allow(unused_imports)]
+pub use sum_to::*;
+#[allow(unused_imports)]
 pub use tanh::*;
+#[allow(unused_imports)]
+pub use top_k::*;
+#[allow(unused_imports)]
+pub use transpose::*;
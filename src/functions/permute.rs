@@ -0,0 +1,155 @@
+use std::iter::once;
+
+use ndarray::IxDyn;
+
+use crate::{
+    element::Element,
+    error::TensorError,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    shape::check_axis,
+    tensor,
+    tensor::{TensorBuilder, TensorRef},
+};
+
+/// Reorders the axes of a tensor: axis `i` of the result is axis `axes[i]` of the input, e.g.
+/// `permute!(x, &[1, 0, 2])` swaps the first two axes.
+#[macro_export]
+macro_rules! permute {
+    ($val1:expr, $axes:expr) => {{
+        use $crate::functions::Permute;
+        use $crate::tensor;
+
+        let t = tensor!($val1.clone());
+
+        let permute = Permute::new($axes);
+        $crate::operation::Operation::apply(&permute, &[t])
+    }};
+}
+
+#[derive(Debug, Clone)]
+pub struct Permute {
+    axes: Vec<usize>,
+}
+
+impl Permute {
+    pub fn new(axes: &[usize]) -> Self {
+        Permute {
+            axes: axes.to_vec(),
+        }
+    }
+
+    /// The permutation of the axes of a tensor of shape `shape` that swaps `axis1` and `axis2`,
+    /// failing when either isn't an axis of it.
+    #[allow(dead_code)]
+    pub fn swapping(shape: &[usize], axis1: usize, axis2: usize) -> Result<Self, TensorError> {
+        check_axis("transpose", shape, axis1)?;
+        check_axis("transpose", shape, axis2)?;
+
+        let axes: Vec<usize> = (0..shape.len())
+            .map(|axis| match axis {
+                _ if axis == axis1 => axis2,
+                _ if axis == axis2 => axis1,
+                _ => axis,
+            })
+            .collect();
+
+        Ok(Permute { axes })
+    }
+
+    /// Axes of the permutation that undoes this one.
+    fn inverse(&self) -> Vec<usize> {
+        let mut inverse = vec![0; self.axes.len()];
+        for (i, &axis) in self.axes.iter().enumerate() {
+            inverse[axis] = i;
+        }
+        inverse
+    }
+}
+
+impl<T: Element> Operation<T> for Permute {
+    fn name(&self) -> &str {
+        "permute"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let a = &inputs[0];
+
+        let a_arr = &a.try_borrow()?.arr;
+        let mut sorted_axes = self.axes.clone();
+        sorted_axes.sort_unstable();
+        if !sorted_axes.iter().copied().eq(0..a_arr.ndim()) {
+            return Err(TensorError::ShapeMismatch {
+                op: "permute".to_string(),
+                lhs: a_arr.shape().to_vec(),
+                rhs: self.axes.clone(),
+            });
+        }
+
+        let permuted = a_arr
+            .view()
+            .permuted_axes(IxDyn(&self.axes))
+            .as_standard_layout()
+            .into_owned();
+        let op_name = new_name("permute");
+
+        Ok(
+            tensor!(permuted, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        _args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        vec![permute!(back_grad, &self.inverse())]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        _args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
+
+        Some(permute!(t, &self.axes))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        let axes: Vec<usize> = once(0)
+            .chain(self.axes.iter().map(|axis| axis + 1))
+            .collect();
+
+        Permute::new(&axes).try_apply(inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some(format!("permute {:?}", self.axes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::TensorError;
+
+    use super::Permute;
+
+    #[test]
+    fn swapping_checks_both_axes() {
+        assert_eq!(
+            Permute::swapping(&[2, 3, 4], 0, 2).unwrap().axes,
+            vec![2, 1, 0]
+        );
+
+        for (axis1, axis2) in [(4, 5), (0, 3)] {
+            let err = Permute::swapping(&[2, 3, 4], axis1, axis2).unwrap_err();
+            assert!(matches!(err, TensorError::ShapeMismatch { op, .. } if op == "transpose"));
+        }
+    }
+}
@@ -1,57 +1,101 @@
-use std::{cell::RefCell, rc::Rc};
-
 use crate::{
-    name_manager::{NameManager, NAME_MANAGER},
-    operation::Operation,
-    tensor,
+    add,
+    element::Element,
+    error::TensorError,
+    functions::Elementwise,
+    name_manager::new_name,
+    operation::{tangents_or_zeros, GradContext, Operation},
+    shape::check_broadcastable,
+    sum_to, tensor,
     tensor::{TensorBuilder, TensorRef},
+    vmap,
 };
 
 #[macro_export]
 macro_rules! prod {
     ($val1:expr, $val2:expr) => {{
-        use crate::functions::Prod;
-        use crate::operation::Operation;
-        use crate::tensor;
+        use $crate::functions::Prod;
+        use $crate::tensor;
 
         let t1 = tensor!($val1.clone());
         let t2 = tensor!($val2.clone());
 
         let prod = Prod::new();
-        prod.apply(&[t1, t2])
+        $crate::operation::Operation::apply(&prod, &[t1, t2])
     }};
 }
 
 #[derive(Debug, Clone)]
-pub struct Prod {
-    name_manager: Rc<RefCell<NameManager>>,
-}
+pub struct Prod;
 
 impl Prod {
     pub fn new() -> Self {
-        Prod {
-            name_manager: NAME_MANAGER.with(|mn| mn.clone()),
-        }
+        Prod
     }
 }
 
-impl Operation for Prod {
-    fn apply(&self, inputs: &[TensorRef]) -> TensorRef {
+impl<T: Element> Operation<T> for Prod {
+    fn name(&self) -> &str {
+        "prod"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
         let a = &inputs[0];
         let b = &inputs[1];
-        let product = &a.borrow().arr * &b.borrow().arr;
-        let op_name = self.name_manager.clone().borrow_mut().new_name("prod");
+        check_broadcastable(
+            "prod",
+            a.try_borrow()?.arr.shape(),
+            b.try_borrow()?.arr.shape(),
+        )?;
 
-        tensor!(product, name: &op_name, parents: vec![a.clone(), b.clone()], operation: Box::new(self.clone()))
+        let product = &a.try_borrow()?.arr * &b.try_borrow()?.arr;
+        let op_name = new_name("prod");
+
+        Ok(
+            tensor!(product, name: &op_name, parents: vec![a.clone(), b.clone()], operation: Box::new(self.clone())),
+        )
     }
 
-    fn grad(&self, back_grad: TensorRef, args: &[TensorRef]) -> Vec<TensorRef> {
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
         let a = &args[0];
         let b = &args[1];
+        let a_shape = a.borrow().arr.shape().to_vec();
+        let b_shape = b.borrow().arr.shape().to_vec();
+
+        vec![
+            sum_to!(prod!(back_grad, b), &a_shape),
+            sum_to!(prod!(back_grad, a), &b_shape),
+        ]
+    }
 
-        let grad_a = tensor!(&back_grad.borrow().arr * &b.borrow().arr, name: "prod_grad");
-        let grad_b = tensor!(&back_grad.borrow().arr * &a.borrow().arr, name: "prod_grad");
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents_or_zeros(tangents, args)?;
+
+        Some(add!(prod!(t[0], args[1]), prod!(args[0], t[1])))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        vmap::apply_elementwise(self, inputs, batched)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some("prod".to_string())
+    }
 
-        vec![grad_a, grad_b]
+    fn elementwise(&self) -> Option<Elementwise> {
+        Some(Elementwise::Prod)
     }
 }
@@ -1,69 +1,103 @@
-use std::{cell::RefCell, rc::Rc};
-
 use crate::{
-    name_manager::{NameManager, NAME_MANAGER},
-    operation::Operation,
-    tensor,
+    element::Element,
+    error::TensorError,
+    functions::Elementwise,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    prod, tensor,
     tensor::{TensorBuilder, TensorRef},
 };
 
 #[macro_export]
 macro_rules! relu {
     ($val1:expr) => {{
-        use crate::functions::ReLU;
-        use crate::operation::Operation;
+        use $crate::functions::ReLU;
 
         let t = tensor!($val1.clone());
 
         let relu = ReLU::new();
-        relu.apply(&[t])
+        $crate::operation::Operation::apply(&relu, &[t])
     }};
 }
 
 #[derive(Debug, Clone)]
-pub struct ReLU {
-    name_manager: Rc<RefCell<NameManager>>,
-}
+pub struct ReLU;
 
 impl ReLU {
     pub fn new() -> Self {
-        ReLU {
-            name_manager: NAME_MANAGER.with(|mn| mn.clone()),
-        }
+        ReLU
     }
 
-    fn apply(x: f64) -> f64 {
-        if x > 0.0 {
+    fn apply<T: Element>(x: T) -> T {
+        if x > T::zero() {
             x
         } else {
-            0.0
+            T::zero()
         }
     }
 
-    fn grad(x: f64) -> f64 {
-        if x > 0.0 {
-            1.0
+    fn grad<T: Element>(x: T) -> T {
+        if x > T::zero() {
+            T::one()
         } else {
-            0.0
+            T::zero()
         }
     }
 }
 
-impl Operation for ReLU {
-    fn apply(&self, inputs: &[TensorRef]) -> TensorRef {
+impl<T: Element> Operation<T> for ReLU {
+    fn name(&self) -> &str {
+        "relu"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
         let a = &inputs[0];
 
-        let relu = a.borrow().arr.mapv(|x| ReLU::apply(x));
-        let op_name = self.name_manager.clone().borrow_mut().new_name("relu");
+        let relu = a.try_borrow()?.arr.mapv(ReLU::apply);
+        let mask = tensor!(a.try_borrow()?.arr.mapv(ReLU::grad), requires_grad: false);
+        let op_name = new_name("relu");
+
+        Ok(
+            tensor!(relu, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone()), saved: vec![mask]),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        _args: &[TensorRef<T>],
+        ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let mask = &ctx.saved()[0];
+
+        vec![prod!(back_grad, mask)]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
+        let mask = tensor!(args[0].borrow().arr.mapv(ReLU::grad), requires_grad: false);
 
-        tensor!(relu, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone()))
+        Some(prod!(t, mask))
     }
 
-    fn grad(&self, back_grad: TensorRef, args: &[TensorRef]) -> Vec<TensorRef> {
-        let a = &args[0];
-        let relu_grad = a.borrow().arr.mapv(|x| ReLU::grad(x)) * &back_grad.borrow().arr;
-        let grad = tensor!(relu_grad, name: "relu_grad");
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        // Elementwise, so the batch axis needs no special treatment.
+        self.try_apply(inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some("relu".to_string())
+    }
 
-        vec![grad]
+    fn elementwise(&self) -> Option<Elementwise> {
+        Some(Elementwise::Relu)
     }
 }
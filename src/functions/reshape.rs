@@ -0,0 +1,100 @@
+use ndarray::IxDyn;
+
+use crate::{
+    element::Element,
+    error::TensorError,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    tensor,
+    tensor::{TensorBuilder, TensorRef},
+    vmap,
+};
+
+/// Gives a tensor a new shape holding the same number of values, read in row-major order,
+/// e.g. `reshape!(x, &[2, 3])`.
+#[macro_export]
+macro_rules! reshape {
+    ($val1:expr, $shape:expr) => {{
+        use $crate::functions::Reshape;
+        use $crate::tensor;
+
+        let t = tensor!($val1.clone());
+
+        let reshape = Reshape::new($shape);
+        $crate::operation::Operation::apply(&reshape, &[t])
+    }};
+}
+
+#[derive(Debug, Clone)]
+pub struct Reshape {
+    shape: Vec<usize>,
+}
+
+impl Reshape {
+    pub fn new(shape: &[usize]) -> Self {
+        Reshape {
+            shape: shape.to_vec(),
+        }
+    }
+}
+
+impl<T: Element> Operation<T> for Reshape {
+    fn name(&self) -> &str {
+        "reshape"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let a = &inputs[0];
+
+        let a_arr = &a.try_borrow()?.arr;
+        let reshaped = a_arr
+            .to_shape(IxDyn(&self.shape))
+            .map_err(|_| TensorError::ShapeMismatch {
+                op: "reshape".to_string(),
+                lhs: a_arr.shape().to_vec(),
+                rhs: self.shape.clone(),
+            })?
+            .into_owned();
+        let op_name = new_name("reshape");
+
+        Ok(
+            tensor!(reshaped, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let input_shape = args[0].borrow().arr.shape().to_vec();
+
+        vec![reshape!(back_grad, &input_shape)]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        _args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
+
+        Some(reshape!(t, &self.shape))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        let mut shape = vec![vmap::batch_size(inputs, batched)];
+        shape.extend(&self.shape);
+
+        Reshape::new(&shape).try_apply(inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some(format!("reshape {:?}", self.shape))
+    }
+}
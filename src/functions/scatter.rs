@@ -0,0 +1,122 @@
+use ndarray::ArrayD;
+
+use crate::{
+    element::Element,
+    error::TensorError,
+    gather,
+    name_manager::new_name,
+    operation::{tangents_or_zeros, GradContext, Operation},
+    shape::{check_index_shape, position},
+    tensor,
+    tensor::{TensorBuilder, TensorRef},
+    vmap,
+};
+
+/// Adds the values of `src` into `x` along `axis` at the positions held by `index`:
+/// `scatter!(x, 1, index, src)`.
+///
+/// `src` has the shape of `index`. Each of its values is added to `x` at the same position,
+/// except along `axis` where the position is read from `index`; positions receiving several
+/// values get their sum. The index receives no gradient.
+#[macro_export]
+macro_rules! scatter {
+    ($val1:expr, $axis:expr, $index:expr, $src:expr) => {{
+        use $crate::functions::Scatter;
+        use $crate::tensor;
+
+        let t = tensor!($val1.clone());
+        let index = tensor!($index.clone());
+        let src = tensor!($src.clone());
+
+        let scatter = Scatter::new($axis);
+        $crate::operation::Operation::apply(&scatter, &[t, index, src])
+    }};
+}
+
+/// Adds values at the positions of an index, the reverse of `Gather`.
+#[derive(Debug, Clone)]
+pub struct Scatter {
+    axis: usize,
+}
+
+impl Scatter {
+    pub fn new(axis: usize) -> Self {
+        Scatter { axis }
+    }
+}
+
+impl<T: Element> Operation<T> for Scatter {
+    fn name(&self) -> &str {
+        "scatter"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let a = &inputs[0];
+        let index = &inputs[1];
+        let src = &inputs[2];
+        let index_arr = &index.try_borrow()?.arr;
+        let src_arr = &src.try_borrow()?.arr;
+        if index_arr.shape() != src_arr.shape() {
+            return Err(TensorError::ShapeMismatch {
+                op: "scatter".to_string(),
+                lhs: index_arr.shape().to_vec(),
+                rhs: src_arr.shape().to_vec(),
+            });
+        }
+
+        let mut scatter = a.try_borrow()?.arr.clone();
+        check_index_shape("scatter", scatter.shape(), index_arr.shape(), self.axis)?;
+
+        let len = scatter.shape()[self.axis];
+        for (idx, &value) in index_arr.indexed_iter() {
+            let mut target = idx.clone();
+            target[self.axis] = position("scatter", value, len)?;
+            scatter[&target] += src_arr[&idx];
+        }
+
+        let op_name = new_name("scatter");
+
+        Ok(
+            tensor!(scatter, name: &op_name, parents: vec![a.clone(), index.clone(), src.clone()], operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let index_zeros = ArrayD::zeros(args[1].borrow().arr.raw_dim());
+
+        vec![
+            back_grad.clone(),
+            tensor!(index_zeros, requires_grad: false),
+            gather!(back_grad, self.axis, args[1]),
+        ]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents_or_zeros(tangents, args)?;
+
+        Some(scatter!(t[0], self.axis, args[1], t[2]))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        let inputs = vmap::with_batch_axis(inputs, batched);
+
+        Scatter::new(self.axis + 1).try_apply(&inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some(format!("scatter {}", self.axis))
+    }
+}
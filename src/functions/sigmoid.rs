@@ -1,61 +1,96 @@
-use std::{cell::RefCell, rc::Rc};
+use ndarray::arr0;
 
 use crate::{
-    name_manager::{NameManager, NAME_MANAGER},
-    operation::Operation,
-    tensor,
+    element::Element,
+    error::TensorError,
+    functions::Elementwise,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    prod, sub, tensor,
     tensor::{TensorBuilder, TensorRef},
 };
 
 #[macro_export]
 macro_rules! sigmoid {
     ($val1:expr) => {{
-        use crate::functions::Sigmoid;
-        use crate::operation::Operation;
+        use $crate::functions::Sigmoid;
 
         let t = tensor!($val1.clone());
 
         let sigmoid = Sigmoid::new();
-        sigmoid.apply(&[t])
+        $crate::operation::Operation::apply(&sigmoid, &[t])
     }};
 }
 
 #[derive(Debug, Clone)]
-pub struct Sigmoid {
-    name_manager: Rc<RefCell<NameManager>>,
-}
+pub struct Sigmoid;
 
 impl Sigmoid {
     pub fn new() -> Self {
-        Sigmoid {
-            name_manager: NAME_MANAGER.with(|mn| mn.clone()),
-        }
+        Sigmoid
     }
 }
 
 impl Sigmoid {
-    fn sigmoid(&self, val: f64) -> f64 {
-        1.0 / (1.0 + (-val).exp())
+    fn sigmoid<T: Element>(&self, val: T) -> T {
+        T::one() / (T::one() + (-val).exp())
     }
 }
 
-impl Operation for Sigmoid {
-    fn apply(&self, inputs: &[TensorRef]) -> TensorRef {
+impl<T: Element> Operation<T> for Sigmoid {
+    fn name(&self) -> &str {
+        "sigmoid"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
         let a = &inputs[0];
 
-        let sigmoid = a.borrow().arr.mapv(|v| self.sigmoid(v));
-        let op_name = self.name_manager.clone().borrow_mut().new_name("sigmoid");
+        let sigmoid = a.try_borrow()?.arr.mapv(|v| self.sigmoid(v));
+        let op_name = new_name("sigmoid");
+
+        Ok(
+            tensor!(sigmoid, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        _args: &[TensorRef<T>],
+        ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let s = ctx.output();
+        let sigmoid_grad = prod!(s, sub!(arr0(T::one()), s));
 
-        tensor!(sigmoid, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone()))
+        vec![prod!(back_grad, sigmoid_grad)]
     }
 
-    fn grad(&self, back_grad: TensorRef, args: &[TensorRef]) -> Vec<TensorRef> {
-        let a = &args[0];
-        let sigmod_result_arr = a.borrow().arr.mapv(|v| self.sigmoid(v));
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
+        let s = sigmoid!(args[0]);
+        let sigmoid_grad = prod!(s, sub!(arr0(T::one()), s));
 
-        let grad_arr = sigmod_result_arr.clone() * (1.0 - sigmod_result_arr);
-        let grad = tensor!(&back_grad.borrow().arr * grad_arr, name: "sigmoid_grad");
+        Some(prod!(t, sigmoid_grad))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        // Elementwise, so the batch axis needs no special treatment.
+        self.try_apply(inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some("sigmoid".to_string())
+    }
 
-        vec![grad]
+    fn elementwise(&self) -> Option<Elementwise> {
+        Some(Elementwise::Sigmoid)
     }
 }
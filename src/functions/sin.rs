@@ -1,53 +1,86 @@
-use std::{cell::RefCell, rc::Rc};
-
 use crate::{
-    name_manager::{NameManager, NAME_MANAGER},
-    operation::Operation,
-    tensor,
+    cos,
+    element::Element,
+    error::TensorError,
+    functions::Elementwise,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    prod, tensor,
     tensor::{TensorBuilder, TensorRef},
 };
 
 #[macro_export]
 macro_rules! sin {
     ($val1:expr) => {{
-        use crate::functions::Sin;
-        use crate::operation::Operation;
+        use $crate::functions::Sin;
 
         let t = tensor!($val1.clone());
 
         let sin = Sin::new();
-        sin.apply(&[t])
+        $crate::operation::Operation::apply(&sin, &[t])
     }};
 }
 
 #[derive(Debug, Clone)]
-pub struct Sin {
-    name_manager: Rc<RefCell<NameManager>>,
-}
+pub struct Sin;
 
 impl Sin {
     pub fn new() -> Self {
-        Sin {
-            name_manager: NAME_MANAGER.with(|mn| mn.clone()),
-        }
+        Sin
     }
 }
 
-impl Operation for Sin {
-    fn apply(&self, inputs: &[TensorRef]) -> TensorRef {
+impl<T: Element> Operation<T> for Sin {
+    fn name(&self) -> &str {
+        "sin"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
         let a = &inputs[0];
 
-        let sin = a.borrow().arr.sin();
-        let op_name = self.name_manager.clone().borrow_mut().new_name("sin");
+        let sin = a.try_borrow()?.arr.sin();
+        let op_name = new_name("sin");
 
-        tensor!(sin, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone()))
+        Ok(
+            tensor!(sin, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone())),
+        )
     }
 
-    fn grad(&self, back_grad: TensorRef, args: &[TensorRef]) -> Vec<TensorRef> {
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
         let a = &args[0];
-        let grad_arr = &back_grad.borrow().arr * a.borrow().arr.cos();
-        let grad = tensor!(grad_arr, name: "sin_grad");
 
-        vec![grad]
+        vec![prod!(back_grad, cos!(a))]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
+
+        Some(prod!(t, cos!(args[0])))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        // Elementwise, so the batch axis needs no special treatment.
+        self.try_apply(inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some("sin".to_string())
+    }
+
+    fn elementwise(&self) -> Option<Elementwise> {
+        Some(Elementwise::Sin)
     }
 }
@@ -0,0 +1,151 @@
+use std::ops::Range;
+
+use ndarray::Axis;
+
+use crate::{
+    element::Element,
+    error::TensorError,
+    functions::IndexSelect,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    shape::check_axis,
+    tensor,
+    tensor::{TensorBuilder, TensorRef},
+};
+
+/// The entries of `x` in `range` along `axis`: `slice!(x, 0, 2..5)`.
+#[macro_export]
+macro_rules! slice {
+    ($val1:expr, $axis:expr, $range:expr) => {{
+        use $crate::functions::Slice;
+        use $crate::tensor;
+
+        let t = tensor!($val1.clone());
+
+        let slice = Slice::new($axis, $range);
+        $crate::operation::Operation::apply(&slice, &[t])
+    }};
+}
+
+#[derive(Debug, Clone)]
+pub struct Slice {
+    axis: usize,
+    start: usize,
+    end: usize,
+}
+
+impl Slice {
+    pub fn new(axis: usize, range: Range<usize>) -> Self {
+        Slice {
+            axis,
+            start: range.start,
+            end: range.end,
+        }
+    }
+}
+
+impl<T: Element> Operation<T> for Slice {
+    fn name(&self) -> &str {
+        "slice"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let a = &inputs[0];
+        let a_arr = &a.try_borrow()?.arr;
+        check_axis("slice", a_arr.shape(), self.axis)?;
+
+        // A start past the end is out of the part of the axis the range leaves it.
+        let len = a_arr.shape()[self.axis];
+        let out_of_bounds = if self.start > len.min(self.end) {
+            Some((self.start, len.min(self.end)))
+        } else if self.end > len {
+            Some((self.end, len))
+        } else {
+            None
+        };
+        if let Some((index, len)) = out_of_bounds {
+            return Err(TensorError::IndexOutOfBounds {
+                op: "slice".to_string(),
+                index: index as i64,
+                len,
+            });
+        }
+
+        let slice = a_arr
+            .slice_axis(Axis(self.axis), (self.start..self.end).into())
+            .to_owned();
+        let op_name = new_name("slice");
+
+        Ok(
+            tensor!(slice, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let input_shape = args[0].borrow().arr.shape().to_vec();
+        let indices: Vec<usize> = (self.start..self.end).collect();
+
+        vec![IndexSelect::spread(
+            &back_grad,
+            &input_shape,
+            self.axis,
+            &indices,
+        )]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        _args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
+
+        Some(slice!(t, self.axis, self.start..self.end))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        Slice::new(self.axis + 1, self.start..self.end).try_apply(inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some(format!("slice {} {}..{}", self.axis, self.start, self.end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use crate::{error::TensorError, operation::Operation, tensor};
+
+    use super::Slice;
+
+    #[test]
+    // A range whose start is past its end is one of the cases checked.
+    #[allow(clippy::reversed_empty_ranges)]
+    fn out_of_bounds_index_is_reported() {
+        let x = tensor!(array![[1.0, 2.0, 3.0]]);
+
+        for (range, index, len) in [(4..5, 4, 3), (1..5, 5, 3), (2..1, 2, 1)] {
+            assert_eq!(
+                Slice::new(1, range)
+                    .try_apply(std::slice::from_ref(&x))
+                    .unwrap_err(),
+                TensorError::IndexOutOfBounds {
+                    op: "slice".to_string(),
+                    index,
+                    len
+                }
+            );
+        }
+    }
+}
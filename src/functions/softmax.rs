@@ -1,82 +1,98 @@
-use std::{cell::RefCell, rc::Rc};
-
-use ndarray::Array2;
+use ndarray::{ArcArray, ArrayD, IxDyn};
 
 use crate::tensor;
 use crate::{
-    name_manager::{NameManager, NAME_MANAGER},
-    operation::Operation,
+    div,
+    element::Element,
+    error::TensorError,
+    exp,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    prod, sub, sum,
     tensor::{TensorBuilder, TensorRef},
+    vmap,
 };
 
 #[macro_export]
 macro_rules! softmax {
     ($val1:expr) => {{
-        use crate::functions::Softmax;
-        use crate::operation::Operation;
-        use crate::tensor;
+        use $crate::functions::Softmax;
+        use $crate::tensor;
 
         let t = tensor!($val1.clone());
 
         let softmax = Softmax::new();
-        softmax.apply(&[t])
+        $crate::operation::Operation::apply(&softmax, &[t])
     }};
 }
 
 #[derive(Debug, Clone)]
-pub struct Softmax {
-    name_manager: Rc<RefCell<NameManager>>,
-}
+pub struct Softmax;
 
 impl Softmax {
     pub fn new() -> Self {
-        Softmax {
-            name_manager: NAME_MANAGER.with(|mn| mn.clone()),
-        }
+        Softmax
     }
 
-    fn apply(inputs: &Array2<f64>) -> Array2<f64> {
+    fn apply<T: Element>(inputs: &ArcArray<T, IxDyn>) -> ArrayD<T> {
         let exps = inputs.mapv(|x| x.exp());
-        let sum_exps: f64 = exps.iter().sum();
+        let sum_exps: T = exps.sum();
         exps / sum_exps
     }
+}
+
+impl<T: Element> Operation<T> for Softmax {
+    fn name(&self) -> &str {
+        "softmax"
+    }
 
-    fn jacobian(xs: &Vec<f64>, ys: &Vec<f64>) -> Array2<f64> {
-        let mut jacobian = Array2::zeros((ys.len(), ys.len()));
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let a = &inputs[0];
 
-        for (k, _) in xs.iter().enumerate() {
-            for (i, _) in ys.iter().enumerate() {
-                if i == k {
-                    jacobian[[i, k]] = ys[i] * (1.0 - ys[i]);
-                } else {
-                    jacobian[[i, k]] = -(ys[k] * ys[i]);
-                }
-            }
-        }
+        let softmax = Softmax::apply(&a.try_borrow()?.arr);
+        let op_name = new_name("softmax");
 
-        jacobian
+        Ok(
+            tensor!(softmax, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone())),
+        )
     }
-}
 
-impl Operation for Softmax {
-    fn apply(&self, inputs: &[TensorRef]) -> TensorRef {
-        let a = &inputs[0];
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        _args: &[TensorRef<T>],
+        ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let y = ctx.output();
+        let weighted_sum = sum!(prod!(back_grad, y));
 
-        let softmax = Softmax::apply(&a.borrow().arr);
-        let op_name = self.name_manager.clone().borrow_mut().new_name("softmax");
+        vec![prod!(y, sub!(back_grad, weighted_sum))]
+    }
 
-        tensor!(softmax, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone()))
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        // The Jacobian of softmax is symmetric, so this is the same product as in `grad`.
+        let t = tangents[0].as_ref()?;
+        let y = softmax!(args[0]);
+        let weighted_sum = sum!(prod!(t, y));
+
+        Some(prod!(y, sub!(t, weighted_sum)))
     }
 
-    fn grad(&self, back_grad: TensorRef, args: &[TensorRef]) -> Vec<TensorRef> {
-        let x = &args[0];
-        let y = Softmax::apply(&x.borrow().arr);
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        let exps = exp!(inputs[0]);
 
-        let xs_flat: Vec<f64> = x.borrow().arr.iter().map(|x| *x).collect();
-        let ys_flat: Vec<f64> = y.iter().map(|y| *y).collect();
-        let j = Self::jacobian(&xs_flat, &ys_flat);
-        let grad = j.t().clone().dot(&back_grad.borrow().arr);
+        Ok(div!(exps, vmap::sum_examples(&exps)))
+    }
 
-        vec![tensor!(grad, name: "softmax_grad")]
+    fn fingerprint(&self) -> Option<String> {
+        Some("softmax".to_string())
     }
 }
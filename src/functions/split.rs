@@ -0,0 +1,149 @@
+use ndarray::{ArrayD, Axis};
+
+use crate::{
+    element::Element,
+    error::TensorError,
+    functions::Concatenate,
+    operation::{MultiOperation, Operation},
+    shape::check_axis,
+    slice, tensor,
+    tensor::{TensorBuilder, TensorRef},
+};
+
+/// Cuts a tensor along `axis` into consecutive parts of the given `sizes`, which add up to the
+/// length of the axis: `let parts = split!(x, 1, &[2, 3]);`.
+#[macro_export]
+macro_rules! split {
+    ($val1:expr, $axis:expr, $sizes:expr) => {{
+        use $crate::functions::Split;
+        use $crate::tensor;
+
+        let t = tensor!($val1.clone());
+
+        let split = Split::new($axis, $sizes);
+        $crate::operation::MultiOperation::apply(&split, &[t])
+    }};
+}
+
+#[derive(Debug, Clone)]
+pub struct Split {
+    axis: usize,
+    sizes: Vec<usize>,
+}
+
+impl Split {
+    pub fn new(axis: usize, sizes: &[usize]) -> Self {
+        Split {
+            axis,
+            sizes: sizes.to_vec(),
+        }
+    }
+}
+
+impl<T: Element> MultiOperation<T> for Split {
+    fn name(&self) -> &str {
+        "split"
+    }
+
+    fn compute(&self, inputs: &[TensorRef<T>]) -> Result<Vec<ArrayD<T>>, TensorError> {
+        let a = inputs[0].try_borrow()?;
+        let shape = a.arr.shape();
+        check_axis("split", shape, self.axis)?;
+        if self.sizes.iter().sum::<usize>() != shape[self.axis] {
+            return Err(TensorError::ShapeMismatch {
+                op: "split".to_string(),
+                lhs: shape.to_vec(),
+                rhs: self.sizes.clone(),
+            });
+        }
+
+        let mut start = 0;
+        let parts = self
+            .sizes
+            .iter()
+            .map(|&size| {
+                let range = (start..start + size).into();
+                start += size;
+                a.arr.slice_axis(Axis(self.axis), range).to_owned()
+            })
+            .collect();
+
+        Ok(parts)
+    }
+
+    fn grad(
+        &self,
+        back_grads: &[Option<TensorRef<T>>],
+        _args: &[TensorRef<T>],
+        outputs: &[TensorRef<T>],
+    ) -> Vec<TensorRef<T>> {
+        let grads: Vec<TensorRef<T>> = back_grads
+            .iter()
+            .zip(outputs)
+            .map(|(grad, output)| match grad {
+                Some(grad) => grad.clone(),
+                None => {
+                    let zeros = ArrayD::zeros(output.borrow().arr.raw_dim());
+                    tensor!(zeros, requires_grad: false)
+                }
+            })
+            .collect();
+
+        vec![Concatenate::new(self.axis).apply(&grads)]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        _args: &[TensorRef<T>],
+        _outputs: &[TensorRef<T>],
+    ) -> Vec<Option<TensorRef<T>>> {
+        let Some(t) = &tangents[0] else {
+            return vec![None; self.sizes.len()];
+        };
+
+        let mut start = 0;
+        self.sizes
+            .iter()
+            .map(|&size| {
+                let part = slice!(t, self.axis, start..start + size);
+                start += size;
+                Some(part)
+            })
+            .collect()
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<Vec<TensorRef<T>>, TensorError> {
+        Split::new(self.axis + 1, &self.sizes).try_apply(inputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use crate::{prod, sum, tensor};
+
+    #[test]
+    fn split_parts_cover_the_axis() {
+        let x = tensor!(array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+
+        let parts = split!(x, 1, &[1, 2]);
+        assert_eq!(parts[0].borrow().arr, array![[1.0], [4.0]].into_dyn());
+        assert_eq!(
+            parts[1].borrow().arr,
+            array![[2.0, 3.0], [5.0, 6.0]].into_dyn()
+        );
+
+        // Only the second part is used: the first one gets a zero gradient.
+        sum!(prod!(parts[1], 2.0)).backward(None);
+        assert_eq!(
+            x.borrow().grad().unwrap().arr,
+            array![[0.0, 2.0, 2.0], [0.0, 2.0, 2.0]].into_dyn()
+        );
+    }
+}
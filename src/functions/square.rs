@@ -1,53 +1,89 @@
-use std::{cell::RefCell, rc::Rc};
+use ndarray::arr0;
 
 use crate::{
-    name_manager::{NameManager, NAME_MANAGER},
-    operation::Operation,
-    tensor,
+    element::Element,
+    error::TensorError,
+    functions::Elementwise,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    prod, tensor,
     tensor::{TensorBuilder, TensorRef},
 };
 
 #[macro_export]
 macro_rules! square {
     ($val1:expr) => {{
-        use crate::functions::Square;
-        use crate::operation::Operation;
+        use $crate::functions::Square;
 
         let t = tensor!($val1.clone());
 
         let square = Square::new();
-        square.apply(&[t])
+        $crate::operation::Operation::apply(&square, &[t])
     }};
 }
 
 #[derive(Debug, Clone)]
-pub struct Square {
-    name_manager: Rc<RefCell<NameManager>>,
-}
+pub struct Square;
 
 impl Square {
     pub fn new() -> Self {
-        Square {
-            name_manager: NAME_MANAGER.with(|mn| mn.clone()),
-        }
+        Square
     }
 }
 
-impl Operation for Square {
-    fn apply(&self, inputs: &[TensorRef]) -> TensorRef {
+impl<T: Element> Operation<T> for Square {
+    fn name(&self) -> &str {
+        "square"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
         let a = &inputs[0];
 
-        let square = a.borrow().arr.mapv(|v| v.powf(2.0));
-        let op_name = self.name_manager.clone().borrow_mut().new_name("square");
+        let square = a.try_borrow()?.arr.mapv(|v| v.powi(2));
+        let op_name = new_name("square");
 
-        tensor!(square, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone()))
+        Ok(
+            tensor!(square, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone())),
+        )
     }
 
-    fn grad(&self, back_grad: TensorRef, args: &[TensorRef]) -> Vec<TensorRef> {
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
         let a = &args[0];
-        let grad_arr = 2.0 * &a.borrow().arr;
-        let grad = tensor!(&back_grad.borrow().arr * grad_arr, name: "square_grad");
+        let twice_a = prod!(a, arr0(T::cast_from(2.0)));
+
+        vec![prod!(back_grad, twice_a)]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
+        let twice_a = prod!(args[0], arr0(T::cast_from(2.0)));
+
+        Some(prod!(t, twice_a))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        // Elementwise, so the batch axis needs no special treatment.
+        self.try_apply(inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some("square".to_string())
+    }
 
-        vec![grad]
+    fn elementwise(&self) -> Option<Elementwise> {
+        Some(Elementwise::Square)
     }
 }
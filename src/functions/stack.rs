@@ -0,0 +1,142 @@
+use ndarray::{ArrayViewD, Axis};
+
+use crate::{
+    element::Element,
+    error::TensorError,
+    name_manager::new_name,
+    operation::{tangents_or_zeros, GradContext, Operation},
+    reshape, slice, tensor,
+    tensor::{TensorBuilder, TensorRef},
+    vmap,
+};
+
+/// Joins tensors of the same shape along a new axis at position `axis`: `stack!(0, a, b)` of
+/// two tensors of shape (2, 3) has shape (2, 2, 3).
+#[macro_export]
+macro_rules! stack {
+    ($axis:expr, $($val:expr),+ $(,)?) => {{
+        use $crate::functions::Stack;
+        use $crate::tensor;
+
+        let inputs = [$(tensor!($val.clone())),+];
+
+        let stack = Stack::new($axis);
+        $crate::operation::Operation::apply(&stack, &inputs)
+    }};
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct Stack {
+    axis: usize,
+}
+
+impl Stack {
+    #[allow(dead_code)]
+    pub fn new(axis: usize) -> Self {
+        Stack { axis }
+    }
+}
+
+impl<T: Element> Operation<T> for Stack {
+    fn name(&self) -> &str {
+        "stack"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let guards = inputs
+            .iter()
+            .map(TensorRef::try_borrow)
+            .collect::<Result<Vec<_>, _>>()?;
+        let views: Vec<ArrayViewD<T>> = guards.iter().map(|guard| guard.arr.view()).collect();
+        let first_shape = views.first().map(|view| view.shape()).unwrap_or_default();
+
+        let stacked = ndarray::stack(Axis(self.axis), &views).map_err(|_| {
+            let other = views.iter().find(|view| view.shape() != first_shape);
+            TensorError::ShapeMismatch {
+                op: "stack".to_string(),
+                lhs: first_shape.to_vec(),
+                rhs: other
+                    .map(|view| view.shape().to_vec())
+                    .unwrap_or_else(|| vec![self.axis]),
+            }
+        })?;
+        drop(guards);
+        let op_name = new_name("stack");
+
+        Ok(
+            tensor!(stacked, name: &op_name, parents: inputs.to_vec(), operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        args.iter()
+            .enumerate()
+            .map(|(i, arg)| {
+                let shape = arg.borrow().arr.shape().to_vec();
+                reshape!(slice!(back_grad, self.axis, i..i + 1), &shape)
+            })
+            .collect()
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let tangents = tangents_or_zeros(tangents, args)?;
+
+        Some(self.apply(&tangents))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        let inputs = vmap::with_batch_axis(inputs, batched);
+
+        Stack::new(self.axis + 1).try_apply(&inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some(format!("stack {}", self.axis))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use crate::{add, sum, tensor, tensor::TensorRef, vmap::vmap};
+
+    #[test]
+    fn batched_examples_are_stacked() {
+        let x = tensor!(array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+
+        // Each example row is stacked with its double.
+        let stacked = vmap(
+            |v: &[TensorRef]| stack!(0, v[0], add!(v[0], v[0])),
+            std::slice::from_ref(&x),
+        );
+        assert_eq!(
+            stacked.borrow().arr,
+            array![
+                [[1.0, 2.0, 3.0], [2.0, 4.0, 6.0]],
+                [[4.0, 5.0, 6.0], [8.0, 10.0, 12.0]]
+            ]
+            .into_dyn()
+        );
+
+        sum!(stacked).backward(None);
+        assert_eq!(
+            x.borrow().grad().unwrap().arr,
+            array![[3.0, 3.0, 3.0], [3.0, 3.0, 3.0]].into_dyn()
+        );
+    }
+}
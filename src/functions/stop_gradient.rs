@@ -0,0 +1,83 @@
+use crate::{
+    element::Element,
+    error::TensorError,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    tensor,
+    tensor::{TensorBuilder, TensorRef},
+};
+
+/// Passes its input through unchanged while blocking its gradient, e.g. for the targets computed
+/// by a target network: `sub!(stop_gradient!(target), predicted)`.
+#[macro_export]
+macro_rules! stop_gradient {
+    ($val1:expr) => {{
+        use $crate::functions::StopGradient;
+        use $crate::tensor;
+
+        let t = tensor!($val1.clone());
+
+        let stop_gradient = StopGradient::new();
+        $crate::operation::Operation::apply(&stop_gradient, &[t])
+    }};
+}
+
+/// Identity whose gradient is dropped. Unlike `TensorRef::detach` it stays in the graph, so the
+/// input remains visible as a parent of the result, but nothing is back-propagated to it.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct StopGradient;
+
+impl StopGradient {
+    #[allow(dead_code)]
+    pub fn new() -> Self {
+        StopGradient
+    }
+}
+
+impl<T: Element> Operation<T> for StopGradient {
+    fn name(&self) -> &str {
+        "stop_gradient"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let a = &inputs[0];
+
+        let value = a.try_borrow()?.arr.clone();
+        let op_name = new_name("stop_gradient");
+
+        Ok(
+            tensor!(value, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        _back_grad: TensorRef<T>,
+        _args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        vec![]
+    }
+
+    fn jvp(
+        &self,
+        _tangents: &[Option<TensorRef<T>>],
+        _args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        None
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        // Elementwise, so the batch axis needs no special treatment.
+        self.try_apply(inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some("stop_gradient".to_string())
+    }
+}
@@ -1,54 +1,101 @@
-use std::{cell::RefCell, rc::Rc};
+use ndarray::arr0;
 
 use crate::{
-    name_manager::{NameManager, NAME_MANAGER},
-    operation::Operation,
-    tensor,
+    element::Element,
+    error::TensorError,
+    functions::Elementwise,
+    name_manager::new_name,
+    operation::{tangents_or_zeros, GradContext, Operation},
+    prod,
+    shape::check_broadcastable,
+    sum_to, tensor,
     tensor::{TensorBuilder, TensorRef},
+    vmap,
 };
 
 #[macro_export]
 macro_rules! sub {
     ($val1:expr, $val2:expr) => {{
-        use crate::functions::Sub;
-        use crate::operation::Operation;
+        use $crate::functions::Sub;
 
         let t1 = tensor!($val1.clone());
         let t2 = tensor!($val2.clone());
 
         let sub = Sub::new();
-        sub.apply(&[t1, t2])
+        $crate::operation::Operation::apply(&sub, &[t1, t2])
     }};
 }
 
 #[derive(Debug, Clone)]
-pub struct Sub {
-    name_manager: Rc<RefCell<NameManager>>,
-}
+pub struct Sub;
 
 impl Sub {
     pub fn new() -> Self {
-        Sub {
-            name_manager: NAME_MANAGER.with(|mn| mn.clone()),
-        }
+        Sub
     }
 }
 
-impl Operation for Sub {
-    fn apply(&self, inputs: &[TensorRef]) -> TensorRef {
+impl<T: Element> Operation<T> for Sub {
+    fn name(&self) -> &str {
+        "sub"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
         let a = &inputs[0];
         let b = &inputs[1];
+        check_broadcastable(
+            "sub",
+            a.try_borrow()?.arr.shape(),
+            b.try_borrow()?.arr.shape(),
+        )?;
+
+        let sub = &a.try_borrow()?.arr - &b.try_borrow()?.arr;
+        let op_name = new_name("sub");
+
+        Ok(
+            tensor!(sub, name: &op_name, parents: vec![a.clone(), b.clone()], operation: Box::new(self.clone())),
+        )
+    }
 
-        let sub = &a.borrow().arr - &b.borrow().arr;
-        let op_name = self.name_manager.clone().borrow_mut().new_name("sub");
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let a_shape = args[0].borrow().arr.shape().to_vec();
+        let b_shape = args[1].borrow().arr.shape().to_vec();
+        let minus_back_grad = prod!(back_grad, arr0(T::cast_from(-1.0)));
 
-        tensor!(sub, name: &op_name, parents: vec![a.clone(), b.clone()], operation: Box::new(self.clone()))
+        vec![
+            sum_to!(back_grad, &a_shape),
+            sum_to!(minus_back_grad, &b_shape),
+        ]
     }
 
-    fn grad(&self, back_grad: TensorRef, _args: &[TensorRef]) -> Vec<TensorRef> {
-        let grad_a = tensor!(back_grad.borrow().arr.clone(), name: "sub_grad");
-        let grad_b = tensor!(&back_grad.borrow().arr * -1.0, name: "sub_grad");
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents_or_zeros(tangents, args)?;
+
+        Some(sub!(t[0], t[1]))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        vmap::apply_elementwise(self, inputs, batched)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some("sub".to_string())
+    }
 
-        vec![grad_a, grad_b]
+    fn elementwise(&self) -> Option<Elementwise> {
+        Some(Elementwise::Sub)
     }
 }
@@ -1,57 +1,107 @@
-use std::{cell::RefCell, rc::Rc};
-
-use ndarray::Array2;
+use ndarray::{ArrayD, IxDyn};
 
 use crate::tensor;
 use crate::{
-    name_manager::{NameManager, NAME_MANAGER},
-    operation::Operation,
+    broadcast_to,
+    element::Element,
+    error::TensorError,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
     tensor::{TensorBuilder, TensorRef},
+    vmap,
 };
 
+/// Sums every value of a tensor. The result keeps the rank of the input with every axis of length
+/// 1, so the sum of a matrix is a 1x1 matrix.
 #[macro_export]
 macro_rules! sum {
     ($val1:expr) => {{
-        use crate::functions::Sum;
-        use crate::operation::Operation;
-        use crate::tensor;
+        use $crate::functions::Sum;
+        use $crate::tensor;
 
         let t = tensor!($val1.clone());
 
         let sum = Sum::new();
-        sum.apply(&[t])
+        $crate::operation::Operation::apply(&sum, &[t])
     }};
 }
 
 #[derive(Debug, Clone)]
-pub struct Sum {
-    name_manager: Rc<RefCell<NameManager>>,
-}
+pub struct Sum;
 
 impl Sum {
     pub fn new() -> Self {
-        Sum {
-            name_manager: NAME_MANAGER.with(|mn| mn.clone()),
-        }
+        Sum
     }
 }
 
-impl Operation for Sum {
-    fn apply(&self, inputs: &[TensorRef]) -> TensorRef {
+impl<T: Element> Operation<T> for Sum {
+    fn name(&self) -> &str {
+        "sum"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
         let a = &inputs[0];
 
-        let sum = a.borrow().arr.sum();
-        let op_name = self.name_manager.clone().borrow_mut().new_name("sum");
+        let a_arr = &a.try_borrow()?.arr;
+        let sum = ArrayD::from_elem(IxDyn(&vec![1; a_arr.ndim()]), a_arr.sum());
+        let op_name = new_name("sum");
+
+        Ok(
+            tensor!(sum, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let input_shape = args[0].borrow().arr.shape().to_vec();
+
+        vec![broadcast_to!(back_grad, &input_shape)]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        _args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
+
+        Some(sum!(t))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        // The only input is the batched one.
+        Ok(vmap::sum_examples(&inputs[0]))
+    }
 
-        tensor!(sum, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone()))
+    fn fingerprint(&self) -> Option<String> {
+        Some("sum".to_string())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, ArrayD};
+
+    use crate::{tensor, tensor::TensorRef, vmap::vmap};
+
+    #[test]
+    fn sum_keeps_the_rank_of_the_input() {
+        let x = tensor!(array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        assert_eq!(sum!(x).borrow().arr[[0, 0]], 21.0);
 
-    fn grad(&self, back_grad: TensorRef, args: &[TensorRef]) -> Vec<TensorRef> {
-        let input_dim = args[0].borrow().arr.raw_dim();
-        let ones_arr = Array2::ones(input_dim);
-        let grad_arr = ones_arr * &back_grad.borrow_mut().arr;
-        let grad = tensor!(grad_arr, name: "sum_grad");
+        let cube = tensor!(ArrayD::from_elem(vec![2, 3, 4], 0.5));
+        assert_eq!(sum!(cube).borrow().arr.shape(), &[1, 1, 1]);
 
-        vec![grad]
+        let sums = vmap(|v: &[TensorRef]| sum!(v[0]), &[x]);
+        assert_eq!(sums.borrow().arr, array![[6.0], [15.0]].into_dyn());
     }
 }
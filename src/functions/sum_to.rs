@@ -0,0 +1,107 @@
+use crate::{
+    broadcast_to,
+    element::Element,
+    error::TensorError,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    reshape,
+    shape::unbroadcast,
+    tensor,
+    tensor::{TensorBuilder, TensorRef},
+    vmap,
+};
+
+#[macro_export]
+macro_rules! sum_to {
+    ($val1:expr, $shape:expr) => {{
+        use $crate::functions::SumTo;
+        use $crate::tensor;
+
+        let t = tensor!($val1.clone());
+
+        let sum_to = SumTo::new($shape);
+        $crate::operation::Operation::apply(&sum_to, &[t])
+    }};
+}
+
+/// Sums a broadcast value back down to `shape`, the reverse of `BroadcastTo`.
+#[derive(Debug, Clone)]
+pub struct SumTo {
+    shape: Vec<usize>,
+}
+
+impl SumTo {
+    pub fn new(shape: &[usize]) -> Self {
+        SumTo {
+            shape: shape.to_vec(),
+        }
+    }
+}
+
+impl<T: Element> Operation<T> for SumTo {
+    fn name(&self) -> &str {
+        "sum_to"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let a = &inputs[0];
+
+        let sum = unbroadcast(a.try_borrow()?.arr.to_owned(), &self.shape);
+        let op_name = new_name("sum_to");
+
+        Ok(
+            tensor!(sum, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let input_shape = args[0].borrow().arr.shape().to_vec();
+
+        vec![broadcast_to!(back_grad, &input_shape)]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        _args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
+
+        Some(sum_to!(t, &self.shape))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        let a = &inputs[0];
+        let batch_size = vmap::batch_size(inputs, batched);
+        let example_shape = vmap::example_shape(a, true);
+        if self.shape.len() > example_shape.len() {
+            return Err(TensorError::ShapeMismatch {
+                op: "sum_to".to_string(),
+                lhs: example_shape,
+                rhs: self.shape.clone(),
+            });
+        }
+
+        // Sums each example with the target padded to its rank, then drops the padding.
+        let mut padded_shape = vec![batch_size];
+        padded_shape.resize(1 + example_shape.len() - self.shape.len(), 1);
+        padded_shape.extend(&self.shape);
+        let mut shape = vec![batch_size];
+        shape.extend(&self.shape);
+
+        Ok(reshape!(sum_to!(a, &padded_shape), &shape))
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some(format!("sum_to {:?}", self.shape))
+    }
+}
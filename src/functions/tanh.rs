@@ -1,55 +1,88 @@
-use std::{cell::RefCell, rc::Rc};
+use ndarray::arr0;
 
 use crate::{
-    name_manager::{NameManager, NAME_MANAGER},
-    operation::Operation,
-    tensor,
+    element::Element,
+    error::TensorError,
+    functions::Elementwise,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    prod, square, sub, tensor,
     tensor::{TensorBuilder, TensorRef},
 };
 
 #[macro_export]
 macro_rules! tanh {
     ($val1:expr) => {{
-        use crate::functions::Tanh;
-        use crate::operation::Operation;
+        use $crate::functions::Tanh;
 
         let t = tensor!($val1.clone());
 
         let tanh = Tanh::new();
-        tanh.apply(&[t])
+        $crate::operation::Operation::apply(&tanh, &[t])
     }};
 }
 
 #[derive(Debug, Clone)]
-pub struct Tanh {
-    name_manager: Rc<RefCell<NameManager>>,
-}
+pub struct Tanh;
 
 impl Tanh {
     pub fn new() -> Self {
-        Tanh {
-            name_manager: NAME_MANAGER.with(|mn| mn.clone()),
-        }
+        Tanh
     }
 }
 
-impl Operation for Tanh {
-    fn apply(&self, inputs: &[TensorRef]) -> TensorRef {
+impl<T: Element> Operation<T> for Tanh {
+    fn name(&self) -> &str {
+        "tanh"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
         let a = &inputs[0];
 
-        let tanh = a.borrow().arr.mapv(f64::tanh);
-        let op_name = self.name_manager.clone().borrow_mut().new_name("tanh");
+        let tanh = a.try_borrow()?.arr.mapv(T::tanh);
+        let op_name = new_name("tanh");
+
+        Ok(
+            tensor!(tanh, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        _args: &[TensorRef<T>],
+        ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let tanh_grad = sub!(arr0(T::one()), square!(ctx.output()));
 
-        tensor!(tanh, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone()))
+        vec![prod!(back_grad, tanh_grad)]
     }
 
-    fn grad(&self, back_grad: TensorRef, args: &[TensorRef]) -> Vec<TensorRef> {
-        let a = &args[0];
-        let tanh_squared = a.borrow().arr.mapv(|v| v.tanh() * v.tanh());
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
+        let tanh_grad = sub!(arr0(T::one()), square!(tanh!(args[0])));
 
-        let grad_arr = 1.0 - tanh_squared;
-        let grad = tensor!(&back_grad.borrow().arr * grad_arr, name: "tanh_grad");
+        Some(prod!(t, tanh_grad))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        // Elementwise, so the batch axis needs no special treatment.
+        self.try_apply(inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some("tanh".to_string())
+    }
 
-        vec![grad]
+    fn elementwise(&self) -> Option<Elementwise> {
+        Some(Elementwise::Tanh)
     }
 }
@@ -0,0 +1,195 @@
+use std::cmp::Ordering;
+
+use ndarray::{ArrayD, Axis, Dimension};
+
+use crate::{
+    element::Element,
+    error::TensorError,
+    operation::MultiOperation,
+    prod, reshape, sum_to, tensor,
+    tensor::{TensorBuilder, TensorRef},
+};
+
+/// The `k` largest values along the last axis, in decreasing order, and their positions:
+/// `let (values, indices) = top_k!(x, 3);`. The indices are stored as floats and receive no
+/// gradient.
+#[macro_export]
+macro_rules! top_k {
+    ($val1:expr, $k:expr) => {{
+        use $crate::functions::TopK;
+        use $crate::tensor;
+
+        let t = tensor!($val1.clone());
+
+        let top_k = TopK::new($k);
+        let mut outputs = $crate::operation::MultiOperation::apply(&top_k, &[t]).into_iter();
+        (outputs.next().unwrap(), outputs.next().unwrap())
+    }};
+}
+
+#[derive(Debug, Clone)]
+pub struct TopK {
+    k: usize,
+}
+
+impl TopK {
+    #[allow(dead_code)]
+    pub fn new(k: usize) -> Self {
+        TopK { k }
+    }
+
+    /// Constant of shape `(..., k, n)` with a one in each row at the index of the selected value,
+    /// so that selecting is a product followed by a sum over the last axis.
+    fn selection<T: Element>(indices: &TensorRef<T>, n: usize) -> TensorRef<T> {
+        let indices = &indices.borrow().arr;
+        let mut shape = indices.shape().to_vec();
+        shape.push(n);
+
+        let selection = ArrayD::from_shape_fn(shape, |idx| {
+            let (column, row) = idx.slice().split_last().expect("selection has an axis");
+            if indices[row].to_usize() == Some(*column) {
+                T::one()
+            } else {
+                T::zero()
+            }
+        });
+        tensor!(selection, requires_grad: false)
+    }
+}
+
+impl<T: Element> MultiOperation<T> for TopK {
+    fn name(&self) -> &str {
+        "top_k"
+    }
+
+    fn compute(&self, inputs: &[TensorRef<T>]) -> Result<Vec<ArrayD<T>>, TensorError> {
+        let a = inputs[0].try_borrow()?;
+        let shape = a.arr.shape();
+        if shape.last().is_none_or(|&n| n < self.k) {
+            return Err(TensorError::ShapeMismatch {
+                op: "top_k".to_string(),
+                lhs: shape.to_vec(),
+                rhs: vec![self.k],
+            });
+        }
+
+        let axis = Axis(shape.len() - 1);
+        let mut out_shape = shape.to_vec();
+        out_shape[axis.index()] = self.k;
+        let mut values = ArrayD::zeros(out_shape.clone());
+        let mut indices = ArrayD::zeros(out_shape);
+
+        for ((lane, mut lane_values), mut lane_indices) in a
+            .arr
+            .lanes(axis)
+            .into_iter()
+            .zip(values.lanes_mut(axis))
+            .zip(indices.lanes_mut(axis))
+        {
+            // Stable, so equal values keep their order.
+            let mut order: Vec<usize> = (0..lane.len()).collect();
+            order.sort_by(|&i, &j| lane[j].partial_cmp(&lane[i]).unwrap_or(Ordering::Equal));
+
+            for (slot, &index) in order[..self.k].iter().enumerate() {
+                lane_values[slot] = lane[index];
+                lane_indices[slot] = T::cast_from(index as f64);
+            }
+        }
+
+        Ok(vec![values, indices])
+    }
+
+    fn grad(
+        &self,
+        back_grads: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+        outputs: &[TensorRef<T>],
+    ) -> Vec<TensorRef<T>> {
+        let Some(values_grad) = &back_grads[0] else {
+            return vec![];
+        };
+
+        let input_shape = args[0].borrow().arr.shape().to_vec();
+        let (&n, batch) = input_shape.split_last().expect("top_k input has an axis");
+        let selection = TopK::selection(&outputs[1], n);
+
+        let column_shape = [batch, &[self.k, 1]].concat();
+        let row_shape = [batch, &[1, n]].concat();
+        let scattered = prod!(reshape!(values_grad, &column_shape), selection);
+
+        vec![reshape!(sum_to!(scattered, &row_shape), &input_shape)]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+        outputs: &[TensorRef<T>],
+    ) -> Vec<Option<TensorRef<T>>> {
+        let Some(t) = &tangents[0] else {
+            return vec![None, None];
+        };
+
+        let input_shape = args[0].borrow().arr.shape().to_vec();
+        let (&n, batch) = input_shape.split_last().expect("top_k input has an axis");
+        let selection = TopK::selection(&outputs[1], n);
+
+        let row_shape = [batch, &[1, n]].concat();
+        let column_shape = [batch, &[self.k, 1]].concat();
+        let selected = prod!(reshape!(t, &row_shape), selection);
+        let values_shape = [batch, &[self.k]].concat();
+
+        vec![
+            Some(reshape!(sum_to!(selected, &column_shape), &values_shape)),
+            None,
+        ]
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<Vec<TensorRef<T>>, TensorError> {
+        // Works along the last axis, so the batch axis needs no special treatment.
+        self.try_apply(inputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use crate::{forward::jvp, sum, tensor, tensor::TensorRef};
+
+    #[test]
+    fn top_k_gradient_reaches_selected_values() {
+        let x = tensor!(array![[3.0, 1.0, 4.0, 1.5], [2.0, 7.0, 1.0, 8.0]]);
+
+        let (values, indices) = top_k!(x, 2);
+        assert_eq!(
+            values.borrow().arr,
+            array![[4.0, 3.0], [8.0, 7.0]].into_dyn()
+        );
+        assert_eq!(
+            indices.borrow().arr,
+            array![[2.0, 0.0], [3.0, 1.0]].into_dyn()
+        );
+
+        // The indices are unused, their gradient is missing.
+        sum!(values).backward(None);
+        assert_eq!(
+            x.borrow().grad().unwrap().arr,
+            array![[1.0, 0.0, 1.0, 0.0], [0.0, 1.0, 0.0, 1.0]].into_dyn()
+        );
+    }
+
+    #[test]
+    fn top_k_tangent_follows_selected_values() {
+        let x = tensor!(array![1.0, 5.0, 3.0]);
+        let t = tensor!(array![10.0, 20.0, 30.0]);
+
+        let (_, tangent) = jvp(|v: &[TensorRef]| top_k!(v[0], 2).0, &[x], &[t]);
+
+        assert_eq!(tangent.borrow().arr, array![20.0, 30.0].into_dyn());
+    }
+}
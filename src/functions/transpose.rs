@@ -0,0 +1,101 @@
+use std::iter::once;
+
+use crate::{
+    element::Element,
+    error::TensorError,
+    functions::Permute,
+    name_manager::new_name,
+    operation::{GradContext, Operation},
+    tensor,
+    tensor::{TensorBuilder, TensorRef},
+};
+
+/// Reverses the order of the axes of a tensor, `transpose!(x)`, or swaps two of them,
+/// `transpose!(x, 0, 2)`.
+#[macro_export]
+macro_rules! transpose {
+    ($val1:expr) => {{
+        use $crate::functions::Transpose;
+        use $crate::tensor;
+
+        let t = tensor!($val1.clone());
+
+        let transpose = Transpose::new();
+        $crate::operation::Operation::apply(&transpose, &[t])
+    }};
+
+    ($val1:expr, $axis1:expr, $axis2:expr) => {{
+        use $crate::functions::Permute;
+        use $crate::tensor;
+
+        let t = tensor!($val1.clone());
+
+        // Axes of a single example inside `vmap`.
+        let shape = $crate::vmap::example_shape(&t, $crate::vmap::is_batched(&t));
+        let permute =
+            Permute::swapping(&shape, $axis1, $axis2).unwrap_or_else(|err| panic!("{err}"));
+        $crate::operation::Operation::apply(&permute, &[t])
+    }};
+}
+
+/// Reverses the order of the axes, i.e. the matrix transpose for 2-D tensors.
+#[derive(Debug, Clone)]
+pub struct Transpose;
+
+impl Transpose {
+    pub fn new() -> Self {
+        Transpose
+    }
+}
+
+impl<T: Element> Operation<T> for Transpose {
+    fn name(&self) -> &str {
+        "transpose"
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let a = &inputs[0];
+
+        let transpose = a.try_borrow()?.arr.t().to_owned();
+        let op_name = new_name("transpose");
+
+        Ok(
+            tensor!(transpose, name: &op_name, parents: vec![a.clone()], operation: Box::new(self.clone())),
+        )
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        _args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        vec![transpose!(back_grad)]
+    }
+
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        _args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        let t = tangents[0].as_ref()?;
+
+        Some(transpose!(t))
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        // Reverses the axes of each example, leaving the batch axis first.
+        let rank = inputs[0].borrow().arr.ndim();
+        let axes: Vec<usize> = once(0).chain((1..rank).rev()).collect();
+
+        Permute::new(&axes).try_apply(inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        Some("transpose".to_string())
+    }
+}
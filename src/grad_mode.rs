@@ -0,0 +1,142 @@
+use std::cell::Cell;
+
+thread_local! {
+    static GRAD_ENABLED: Cell<bool> = const { Cell::new(true) };
+    static INFERENCE_MODE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether operations on this thread record their parents and themselves for back-propagation.
+pub fn is_grad_enabled() -> bool {
+    GRAD_ENABLED.with(|g| g.get())
+}
+
+/// Whether this thread is in inference mode, where nothing is recorded and no names are handed
+/// out by the `NameManager`.
+pub fn is_inference_mode() -> bool {
+    INFERENCE_MODE.with(|i| i.get())
+}
+
+/// Sets whether gradients are recorded until dropped, then restores the previous setting. While
+/// they're disabled, operations produce tensors without parents or operation that don't require
+/// a gradient.
+pub struct GradModeGuard {
+    previous: bool,
+}
+
+impl GradModeGuard {
+    pub fn new(enabled: bool) -> Self {
+        let previous = GRAD_ENABLED.with(|g| g.replace(enabled));
+        GradModeGuard { previous }
+    }
+
+    pub fn no_grad() -> Self {
+        Self::new(false)
+    }
+}
+
+impl Drop for GradModeGuard {
+    fn drop(&mut self) {
+        GRAD_ENABLED.with(|g| g.set(self.previous));
+    }
+}
+
+/// Disables gradient recording and name bookkeeping until dropped.
+pub struct InferenceModeGuard {
+    _grad_mode: GradModeGuard,
+    previous: bool,
+}
+
+impl InferenceModeGuard {
+    pub fn new() -> Self {
+        let grad_mode = GradModeGuard::no_grad();
+        let previous = INFERENCE_MODE.with(|i| i.replace(true));
+
+        InferenceModeGuard {
+            _grad_mode: grad_mode,
+            previous,
+        }
+    }
+}
+
+impl Drop for InferenceModeGuard {
+    fn drop(&mut self) {
+        INFERENCE_MODE.with(|i| i.set(self.previous));
+    }
+}
+
+/// Runs `f` without recording gradients.
+pub fn no_grad<R>(f: impl FnOnce() -> R) -> R {
+    let _guard = GradModeGuard::no_grad();
+    f()
+}
+
+/// Runs `f` in inference mode.
+pub fn inference_mode<R>(f: impl FnOnce() -> R) -> R {
+    let _guard = InferenceModeGuard::new();
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{prod, tensor, tensor::TensorRef};
+
+    use super::{is_grad_enabled, is_inference_mode, GradModeGuard, InferenceModeGuard};
+
+    fn assert_unrecorded(tensor: &TensorRef) {
+        let tensor = tensor.borrow();
+        assert!(!tensor.requires_grad);
+        assert!(tensor.parents.is_empty());
+        assert!(tensor.operation.is_none());
+    }
+
+    #[test]
+    fn no_grad_results_are_not_recorded() {
+        let x = tensor!(3.0);
+
+        let y = {
+            let _no_grad = GradModeGuard::no_grad();
+            prod!(x, x)
+        };
+
+        assert_eq!(y.borrow().arr[[0, 0]], 9.0);
+        assert_unrecorded(&y);
+        assert!(!prod!(x, x).borrow().parents.is_empty());
+    }
+
+    #[test]
+    fn guards_restore_the_previous_mode() {
+        assert!(is_grad_enabled());
+        {
+            let _outer = GradModeGuard::no_grad();
+            assert!(!is_grad_enabled());
+            {
+                let _inner = GradModeGuard::new(true);
+                assert!(is_grad_enabled());
+            }
+            assert!(!is_grad_enabled());
+            {
+                let _inner = GradModeGuard::no_grad();
+                assert!(!is_grad_enabled());
+            }
+            assert!(!is_grad_enabled());
+        }
+        assert!(is_grad_enabled());
+    }
+
+    #[test]
+    fn inference_mode_disables_recording() {
+        let x = tensor!(3.0);
+
+        let y = {
+            let _inference = InferenceModeGuard::new();
+            assert!(is_inference_mode());
+            assert!(!is_grad_enabled());
+            prod!(x, x)
+        };
+
+        assert_unrecorded(&y);
+        assert_eq!(y.borrow().name.as_deref(), Some("prod"));
+        assert!(!is_inference_mode());
+        assert!(is_grad_enabled());
+    }
+}
@@ -0,0 +1,370 @@
+//! Numerical checking of gradient rules.
+//!
+//! `gradcheck` compares the gradients a backward pass computes with central finite differences.
+//! A non-scalar output is first reduced to a weighted sum with fixed, distinct weights, so that a
+//! gradient rule can't pass by accident on outputs whose plain sum is constant, like `softmax!`.
+
+use std::fmt;
+
+use ndarray::{ArrayD, Dimension, IxDyn};
+
+use crate::{element::Element, functional::grad, grad_mode::no_grad, prod, sum, tensor::TensorRef};
+
+/// Settings for `gradcheck_with`.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct GradcheckOptions {
+    /// Step of the finite differences.
+    pub eps: f64,
+    /// Absolute tolerance between the analytical and numerical gradients.
+    pub atol: f64,
+    /// Tolerance relative to the numerical gradient, added to `atol`.
+    pub rtol: f64,
+}
+
+impl Default for GradcheckOptions {
+    fn default() -> Self {
+        GradcheckOptions {
+            eps: 1e-6,
+            atol: 1e-5,
+            rtol: 1e-3,
+        }
+    }
+}
+
+/// The first input element whose analytical gradient disagrees with the numerical one.
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradcheckMismatch {
+    /// Position of the input in the `inputs` given to `gradcheck`.
+    pub input: usize,
+    /// Index of the element in that input.
+    pub index: Vec<usize>,
+    pub analytical: f64,
+    pub numerical: f64,
+}
+
+impl fmt::Display for GradcheckMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "gradcheck: gradient of input {} at {:?} is {}, finite differences give {}",
+            self.input, self.index, self.analytical, self.numerical
+        )
+    }
+}
+
+/// `gradcheck_with` using the default tolerances.
+#[allow(dead_code)]
+pub fn gradcheck<T, F>(f: F, inputs: &[TensorRef<T>]) -> Result<(), GradcheckMismatch>
+where
+    T: Element,
+    F: Fn(&[TensorRef<T>]) -> TensorRef<T>,
+{
+    gradcheck_with(f, inputs, GradcheckOptions::default())
+}
+
+/// Checks the gradients of the output of `f` with respect to each of `inputs`, element by
+/// element. `f` is run twice per element, so inputs should be small.
+///
+/// Use `f64` inputs: the finite differences of `f32` values are too inaccurate for the default
+/// tolerances. Inputs should also stay away from the points where `f` isn't differentiable, like
+/// 0 for `relu!`. The values and gradients the inputs hold are left untouched.
+#[allow(dead_code)]
+pub fn gradcheck_with<T, F>(
+    f: F,
+    inputs: &[TensorRef<T>],
+    options: GradcheckOptions,
+) -> Result<(), GradcheckMismatch>
+where
+    T: Element,
+    F: Fn(&[TensorRef<T>]) -> TensorRef<T>,
+{
+    let output_dim = no_grad(|| f(inputs)).borrow().arr.raw_dim();
+    let weights = ArrayD::from_shape_fn(output_dim, |idx| {
+        let position = idx.slice().iter().fold(0, |acc, &i| acc * 7 + i + 1) as f64;
+        T::cast_from(0.5 + (position * 0.618).fract())
+    });
+
+    let grads = grad(|inputs| sum!(prod!(f(inputs), weights)), inputs);
+    let value = |inputs: &[TensorRef<T>]| no_grad(|| (&f(inputs).borrow().arr * &weights).sum());
+    let eps = T::cast_from(options.eps);
+
+    for (input_index, (input, grad)) in inputs.iter().zip(&grads).enumerate() {
+        let indices: Vec<IxDyn> = input
+            .borrow()
+            .arr
+            .indexed_iter()
+            .map(|(idx, _)| idx)
+            .collect();
+
+        for idx in indices {
+            let original = input.borrow().arr[&idx];
+
+            input.borrow_mut().arr[&idx] = original + eps;
+            let plus = value(inputs);
+            input.borrow_mut().arr[&idx] = original - eps;
+            let minus = value(inputs);
+            input.borrow_mut().arr[&idx] = original;
+
+            let numerical = f64::convert((plus - minus) / (eps + eps));
+            let analytical = f64::convert(grad.borrow().arr[&idx]);
+            if (analytical - numerical).abs() > options.atol + options.rtol * numerical.abs() {
+                return Err(GradcheckMismatch {
+                    input: input_index,
+                    index: idx.slice().to_vec(),
+                    analytical,
+                    numerical,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use crate::{
+        add, broadcast_to, cast, concatenate, cos, custom, div, exp,
+        functions::{CustomOp, Elementwise, Fused, Operand, Step},
+        gather, index_select, ln, masked_select, matmul,
+        operation::Operation,
+        permute, prod, relu, reshape, scatter, sigmoid, sin, slice, softmax, split, square, stack,
+        stop_gradient, sub, sum, sum_to, tanh, tensor,
+        tensor::TensorRef,
+        top_k, transpose,
+    };
+
+    use super::{gradcheck, gradcheck_with, GradcheckOptions};
+
+    fn check(f: impl Fn(&[TensorRef]) -> TensorRef, inputs: &[TensorRef]) {
+        if let Err(mismatch) = gradcheck(f, inputs) {
+            panic!("{mismatch}");
+        }
+    }
+
+    fn matrix() -> TensorRef {
+        tensor!(array![[0.3, -1.2, 0.8], [1.5, -0.4, 2.1]])
+    }
+
+    fn positive_matrix() -> TensorRef {
+        tensor!(array![[0.3, 1.2, 0.8], [1.5, 0.4, 2.1]])
+    }
+
+    fn column() -> TensorRef {
+        tensor!(array![[0.7], [-1.3]])
+    }
+
+    #[test]
+    fn add() {
+        check(|v| add!(v[0], v[1]), &[matrix(), column()]);
+    }
+
+    #[test]
+    fn sub() {
+        check(|v| sub!(v[0], v[1]), &[matrix(), column()]);
+    }
+
+    #[test]
+    fn prod() {
+        check(|v| prod!(v[0], v[1]), &[matrix(), column()]);
+    }
+
+    #[test]
+    fn div() {
+        check(|v| div!(v[0], v[1]), &[column(), positive_matrix()]);
+    }
+
+    #[test]
+    fn matmul() {
+        let rhs = tensor!(array![[0.5, -1.0], [2.0, 0.25], [-0.75, 1.5]]);
+        check(|v| matmul!(v[0], v[1]), &[matrix(), rhs]);
+    }
+
+    #[test]
+    fn exp() {
+        check(|v| exp!(v[0]), &[matrix()]);
+    }
+
+    #[test]
+    fn ln() {
+        check(|v| ln!(v[0]), &[positive_matrix()]);
+    }
+
+    #[test]
+    fn sin() {
+        check(|v| sin!(v[0]), &[matrix()]);
+    }
+
+    #[test]
+    fn cos() {
+        check(|v| cos!(v[0]), &[matrix()]);
+    }
+
+    #[test]
+    fn square() {
+        check(|v| square!(v[0]), &[matrix()]);
+    }
+
+    #[test]
+    fn relu() {
+        check(|v| relu!(v[0]), &[matrix()]);
+    }
+
+    #[test]
+    fn sigmoid() {
+        check(|v| sigmoid!(v[0]), &[matrix()]);
+    }
+
+    #[test]
+    fn tanh() {
+        check(|v| tanh!(v[0]), &[matrix()]);
+    }
+
+    #[test]
+    fn softmax() {
+        check(|v| softmax!(v[0]), &[matrix()]);
+    }
+
+    #[test]
+    fn sum() {
+        check(|v| sum!(v[0]), &[matrix()]);
+    }
+
+    #[test]
+    fn sum_to() {
+        check(|v| sum_to!(v[0], &[1, 3]), &[matrix()]);
+    }
+
+    #[test]
+    fn broadcast_to() {
+        check(|v| broadcast_to!(v[0], &[2, 3]), &[column()]);
+    }
+
+    #[test]
+    fn reshape() {
+        check(|v| reshape!(v[0], &[3, 2]), &[matrix()]);
+    }
+
+    #[test]
+    fn permute() {
+        let x = tensor!(array![
+            [[0.3, -1.2], [0.8, 1.5]],
+            [[-0.4, 2.1], [0.6, -0.9]]
+        ]);
+        check(|v| permute!(v[0], &[2, 0, 1]), &[x]);
+    }
+
+    #[test]
+    fn transpose() {
+        check(|v| transpose!(v[0]), &[matrix()]);
+    }
+
+    #[test]
+    fn transpose_axes() {
+        let x = tensor!(array![
+            [[0.3, -1.2], [0.8, 1.5]],
+            [[-0.4, 2.1], [0.6, -0.9]]
+        ]);
+        check(|v| transpose!(v[0], 0, 2), &[x]);
+    }
+
+    #[test]
+    fn concatenate() {
+        check(|v| concatenate!(1, v[0], v[1]), &[matrix(), column()]);
+    }
+
+    #[test]
+    fn stack() {
+        check(|v| stack!(1, v[0], v[1]), &[matrix(), positive_matrix()]);
+    }
+
+    #[test]
+    fn split() {
+        check(|v| split!(v[0], 1, &[1, 2])[1].clone(), &[matrix()]);
+    }
+
+    #[test]
+    fn slice() {
+        check(|v| slice!(v[0], 1, 1..3), &[matrix()]);
+    }
+
+    #[test]
+    fn index_select() {
+        check(|v| index_select!(v[0], 1, &[2, 0, 2]), &[matrix()]);
+    }
+
+    #[test]
+    fn gather() {
+        let index = tensor!(array![[2.0, 2.0], [0.0, 1.0]]);
+        check(|v| gather!(v[0], 1, index), &[matrix()]);
+    }
+
+    #[test]
+    fn scatter() {
+        let index = tensor!(array![[2.0, 2.0], [0.0, 1.0]]);
+        let src = tensor!(array![[0.5, -1.5], [2.5, 1.0]]);
+        check(|v| scatter!(v[0], 1, index, v[1]), &[matrix(), src]);
+    }
+
+    #[test]
+    fn masked_select() {
+        let mask = tensor!(array![[1.0, 0.0, 1.0], [0.0, 0.0, 1.0]]);
+        check(|v| masked_select!(v[0], mask), &[matrix()]);
+    }
+
+    #[test]
+    fn top_k() {
+        check(|v| top_k!(v[0], 2).0, &[matrix()]);
+    }
+
+    #[test]
+    fn cast() {
+        // The round trip through `f32` loses precision, so the steps have to be larger.
+        let options = GradcheckOptions {
+            eps: 1e-2,
+            atol: 1e-4,
+            ..Default::default()
+        };
+        let result = gradcheck_with(|v| cast!(cast!(v[0], f32), f64), &[matrix()], options);
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn custom() {
+        let cube = CustomOp::new("cube", |x| x[0].mapv(|v| v * v * v))
+            .backward(|back_grad, args, _ctx| vec![prod!(back_grad, prod!(square!(args[0]), 3.0))]);
+        check(|v| custom!(cube, v[0]), &[matrix()]);
+    }
+
+    #[test]
+    fn fused() {
+        let fused = Fused::new(vec![
+            Step {
+                op: Elementwise::Div,
+                operands: vec![Operand::Input(0), Operand::Input(1)],
+            },
+            Step {
+                op: Elementwise::Tanh,
+                operands: vec![Operand::Step(0)],
+            },
+            Step {
+                op: Elementwise::Prod,
+                operands: vec![Operand::Step(1), Operand::Input(0)],
+            },
+        ]);
+        check(|v| fused.apply(v), &[matrix(), column()]);
+    }
+
+    #[test]
+    fn stop_gradient_is_reported() {
+        let mismatch = gradcheck(|v| stop_gradient!(v[0]), &[column()]).unwrap_err();
+
+        assert_eq!((mismatch.input, mismatch.index), (0, vec![0, 0]));
+        assert_eq!(mismatch.analytical, 0.0);
+        assert!(mismatch.numerical != 0.0);
+    }
+}
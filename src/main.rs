@@ -2,11 +2,22 @@ use crate::examples::{
     perform_image_recognition, perform_sin_regression, perform_sin_regression_mlp,
 };
 
+mod dot;
+mod element;
+mod error;
 mod examples;
+mod forward;
+mod functional;
 mod functions;
+mod grad_mode;
+mod gradcheck;
+mod multi_output;
 mod name_manager;
 mod operation;
+mod shape;
 mod tensor;
+mod trace;
+mod vmap;
 
 fn main() {
     perform_sin_regression();
@@ -0,0 +1,332 @@
+//! Operations with several outputs.
+//!
+//! A `MultiOperation` is recorded as a hidden node computed from the inputs, which holds the
+//! values of all the outputs flattened one after the other, and one node per output reading its
+//! part back. During back-propagation the output nodes hand their gradients over to the hidden
+//! node instead of passing them on. The hidden node comes after all of them in topological
+//! order, so when it's reached it runs the gradient rule once with the gradients of every
+//! output, `None` for the outputs that received none.
+
+use std::{any::Any, sync::Arc};
+
+use ndarray::{Array1, ArrayD, IxDyn};
+
+use crate::{
+    add,
+    element::Element,
+    error::TensorError,
+    forward::{is_forward_mode, output_tangents},
+    name_manager::new_name,
+    operation::{GradContext, MultiOperation, Operation},
+    tensor,
+    tensor::{Shared, TensorBuilder, TensorRef},
+};
+
+/// Gradients handed over by the output nodes, one slot per output.
+type GradSlots<T> = Shared<Vec<Option<TensorRef<T>>>>;
+
+/// Computes the outputs of `op` and records them in the graph, see the module documentation.
+pub fn try_apply<T, O>(op: &O, inputs: &[TensorRef<T>]) -> Result<Vec<TensorRef<T>>, TensorError>
+where
+    T: Element,
+    O: MultiOperation<T> + Clone + 'static,
+{
+    let arrays = op.compute(inputs)?;
+    let outputs = Outputs::new(Arc::new(op.clone()), &arrays);
+    let node = outputs.record(inputs, &arrays);
+
+    let outputs: Vec<TensorRef<T>> = arrays
+        .into_iter()
+        .enumerate()
+        .map(|(index, arr)| outputs.output(index).record(&node, arr))
+        .collect();
+
+    if is_forward_mode() {
+        for (output, tangent) in outputs.iter().zip(output_tangents(op, inputs, &outputs)) {
+            output.borrow_mut().tangent = tangent;
+        }
+    }
+
+    Ok(outputs)
+}
+
+/// Operation of the hidden node of a `MultiOperation`.
+#[derive(Debug, Clone)]
+pub struct Outputs<T: Element> {
+    op: Arc<dyn MultiOperation<T>>,
+    shapes: Vec<Vec<usize>>,
+    grads: GradSlots<T>,
+}
+
+impl<T: Element> Outputs<T> {
+    fn new(op: Arc<dyn MultiOperation<T>>, arrays: &[ArrayD<T>]) -> Self {
+        Outputs {
+            op,
+            shapes: arrays.iter().map(|arr| arr.shape().to_vec()).collect(),
+            grads: Shared::new(vec![None; arrays.len()]),
+        }
+    }
+
+    /// Records the hidden node holding `arrays`, the values of the outputs.
+    fn record(&self, inputs: &[TensorRef<T>], arrays: &[ArrayD<T>]) -> TensorRef<T> {
+        let values: Array1<T> = arrays.iter().flatten().copied().collect();
+        let op_name = new_name(self.op.name());
+
+        tensor!(values, name: &op_name, parents: inputs.to_vec(), operation: Box::new(self.clone()))
+    }
+
+    /// Operation of the node reading the output at `index` back.
+    fn output(&self, index: usize) -> OutputOf<T> {
+        OutputOf {
+            index,
+            offset: self.shapes[..index]
+                .iter()
+                .map(|shape| shape.iter().product::<usize>())
+                .sum(),
+            shape: self.shapes[index].clone(),
+            op_name: self.op.name().to_string(),
+            grads: self.grads.clone(),
+        }
+    }
+}
+
+impl<T: Element> Operation<T> for Outputs<T> {
+    fn name(&self) -> &str {
+        self.op.name()
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        // A new group of nodes, e.g. a trace replay, gets its own gradient slots.
+        let arrays = self.op.compute(inputs)?;
+        let outputs = Outputs::new(self.op.clone(), &arrays);
+
+        Ok(outputs.record(inputs, &arrays))
+    }
+
+    fn grad(
+        &self,
+        _back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let back_grads = std::mem::replace(&mut *self.grads.write(), vec![None; self.shapes.len()]);
+        let node = std::slice::from_ref(ctx.output());
+        let outputs: Vec<TensorRef<T>> = (0..self.shapes.len())
+            .map(|index| {
+                let output = self.output(index);
+                let arr = output.read(&node[0]).unwrap_or_else(|err| panic!("{err}"));
+                output.record(&node[0], arr)
+            })
+            .collect();
+
+        self.op.grad(&back_grads, args, &outputs)
+    }
+
+    fn jvp(
+        &self,
+        _tangents: &[Option<TensorRef<T>>],
+        _args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        // The output nodes get their tangents from `MultiOperation::jvp` directly.
+        None
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        // Not reached: batched inputs go through `MultiOperation::apply_batched`.
+        self.try_apply(inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        // Each group of nodes hands gradients over through its own slots.
+        None
+    }
+}
+
+/// Operation of the node reading one output of a `MultiOperation` back from the hidden node.
+#[derive(Debug, Clone)]
+pub struct OutputOf<T: Element> {
+    index: usize,
+    offset: usize,
+    shape: Vec<usize>,
+    op_name: String,
+    grads: GradSlots<T>,
+}
+
+impl<T: Element> OutputOf<T> {
+    /// Values of the output, read from the hidden node `node`.
+    fn read(&self, node: &TensorRef<T>) -> Result<ArrayD<T>, TensorError> {
+        let len = self.shape.iter().product();
+        let values: Vec<T> = node
+            .try_borrow()?
+            .arr
+            .iter()
+            .skip(self.offset)
+            .take(len)
+            .copied()
+            .collect();
+
+        ArrayD::from_shape_vec(IxDyn(&self.shape), values).map_err(|_| TensorError::ShapeMismatch {
+            op: self.op_name.clone(),
+            lhs: node.borrow().arr.shape().to_vec(),
+            rhs: self.shape.clone(),
+        })
+    }
+
+    fn record(self, node: &TensorRef<T>, arr: ArrayD<T>) -> TensorRef<T> {
+        let op_name = new_name(&self.op_name);
+
+        tensor!(arr, name: &op_name, parents: vec![node.clone()], operation: Box::new(self))
+    }
+}
+
+impl<T: Element> Operation<T> for OutputOf<T> {
+    fn name(&self) -> &str {
+        &self.op_name
+    }
+
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let node = &inputs[0];
+        let arr = self.read(node)?;
+
+        // The output hands its gradients over to the hidden node it's applied to, which isn't
+        // the one it was recorded with when the group is rebuilt, e.g. by a trace replay.
+        let grads = match node
+            .try_borrow()?
+            .operation
+            .as_deref()
+            .and_then(|op| (op as &dyn Any).downcast_ref::<Outputs<T>>())
+        {
+            Some(outputs) => outputs.grads.clone(),
+            // A hidden node folded into a constant receives no gradient.
+            None => Shared::new(vec![None; self.index + 1]),
+        };
+        let output = OutputOf {
+            grads,
+            ..self.clone()
+        };
+
+        Ok(output.record(node, arr))
+    }
+
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        _ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>> {
+        let mut grads = self.grads.write();
+        let slot = &mut grads[self.index];
+        *slot = Some(match slot.take() {
+            Some(existing) => add!(existing, back_grad),
+            None => back_grad,
+        });
+        drop(grads);
+
+        // The hidden node only needs some gradient to be visited; its rule uses the slots.
+        let placeholder = ArrayD::zeros(args[0].borrow().arr.raw_dim());
+        vec![tensor!(placeholder, requires_grad: false)]
+    }
+
+    fn jvp(
+        &self,
+        _tangents: &[Option<TensorRef<T>>],
+        _args: &[TensorRef<T>],
+    ) -> Option<TensorRef<T>> {
+        // Set by `multi_output::try_apply` from `MultiOperation::jvp`.
+        None
+    }
+
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        _batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError> {
+        // Not reached: batched inputs go through `MultiOperation::apply_batched`.
+        self.try_apply(inputs)
+    }
+
+    fn fingerprint(&self) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+
+    use ndarray::array;
+
+    use crate::{
+        add, prod, sum, tensor,
+        tensor::{BackwardOptions, TensorRef},
+        top_k,
+        trace::trace,
+    };
+
+    use super::{OutputOf, Outputs};
+
+    #[test]
+    fn output_used_twice_is_back_propagated_once_per_pass() {
+        let x = tensor!(array![[1.0, 3.0, 2.0]]);
+        let (values, _) = top_k!(x, 1);
+        let y = sum!(add!(values, values));
+
+        let options = BackwardOptions {
+            retain_graph: true,
+            ..Default::default()
+        };
+        y.backward_with(None, options);
+        assert_eq!(
+            x.borrow().grad().unwrap().arr,
+            array![[0.0, 2.0, 0.0]].into_dyn()
+        );
+
+        y.backward(None);
+        assert_eq!(
+            x.borrow().grad().unwrap().arr,
+            array![[0.0, 4.0, 0.0]].into_dyn()
+        );
+    }
+
+    /// Address of the gradient slots of the group `tensor` belongs to.
+    fn slots_of(tensor: &TensorRef) -> *const () {
+        let tensor = tensor.borrow();
+        let operation = tensor.operation.as_deref().expect("node has an operation") as &dyn Any;
+        if let Some(outputs) = operation.downcast_ref::<Outputs<f64>>() {
+            outputs.grads.as_ptr()
+        } else {
+            let output = operation.downcast_ref::<OutputOf<f64>>();
+            output.expect("node of a multi-output op").grads.as_ptr()
+        }
+    }
+
+    #[test]
+    fn replayed_groups_keep_their_gradients_apart() {
+        let traced = trace(
+            |v: &[TensorRef]| top_k!(v[0], 1).0,
+            &[tensor!(array![[0.0, 0.0, 0.0]])],
+        );
+        let x = tensor!(array![[1.0, 3.0, 2.0]]);
+        let y = tensor!(array![[5.0, 4.0, 6.0]]);
+
+        let replayed_x = traced.run(std::slice::from_ref(&x));
+        let replayed_y = traced.run(std::slice::from_ref(&y));
+        let hidden_x = replayed_x.borrow().parents[0].clone();
+        assert_eq!(slots_of(&replayed_x), slots_of(&hidden_x));
+        assert_ne!(slots_of(&replayed_x), slots_of(&replayed_y));
+
+        sum!(add!(replayed_x, prod!(replayed_y, 2.0))).backward(None);
+        assert_eq!(
+            x.borrow().grad().unwrap().arr,
+            array![[0.0, 1.0, 0.0]].into_dyn()
+        );
+        assert_eq!(
+            y.borrow().grad().unwrap().arr,
+            array![[0.0, 0.0, 2.0]].into_dyn()
+        );
+    }
+}
@@ -1,4 +1,6 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{cell::Cell, collections::HashMap};
+
+use crate::grad_mode::is_inference_mode;
 
 #[derive(Debug)]
 pub struct NameManager {
@@ -25,6 +27,63 @@ impl NameManager {
     }
 }
 
+#[cfg(not(feature = "sync"))]
+thread_local! {
+    static NAME_MANAGER: std::cell::RefCell<NameManager> = std::cell::RefCell::new(NameManager::new());
+}
+
+/// With the `sync` feature names are unique across all threads, as graphs may span several.
+#[cfg(feature = "sync")]
+static NAME_MANAGER: std::sync::LazyLock<std::sync::Mutex<NameManager>> =
+    std::sync::LazyLock::new(|| std::sync::Mutex::new(NameManager::new()));
+
+#[cfg(not(feature = "sync"))]
+fn with_name_manager<R>(f: impl FnOnce(&mut NameManager) -> R) -> R {
+    NAME_MANAGER.with(|nm| f(&mut nm.borrow_mut()))
+}
+
+#[cfg(feature = "sync")]
+fn with_name_manager<R>(f: impl FnOnce(&mut NameManager) -> R) -> R {
+    let mut name_manager = NAME_MANAGER.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut name_manager)
+}
+
 thread_local! {
-    pub(crate) static NAME_MANAGER: Rc<RefCell<NameManager>> = Rc::new(RefCell::new(NameManager::new()));
+    static NAMES_PAUSED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Makes `new_name` return names as is on this thread until dropped, for callers that name the
+/// tensors themselves, like `Trace::run`.
+pub struct PausedNamesGuard {
+    previous: bool,
+}
+
+impl PausedNamesGuard {
+    pub fn new() -> Self {
+        let previous = NAMES_PAUSED.with(|p| p.replace(true));
+        PausedNamesGuard { previous }
+    }
+}
+
+impl Drop for PausedNamesGuard {
+    fn drop(&mut self) {
+        NAMES_PAUSED.with(|p| p.set(self.previous));
+    }
+}
+
+/// Returns a unique name for a new tensor computed by an operation of kind `name`, such as
+/// `add:3`. In inference mode, or while names are paused, the shared `NameManager` isn't touched
+/// and `name` is returned as is.
+pub fn new_name(name: &str) -> String {
+    if is_inference_mode() || NAMES_PAUSED.with(|p| p.get()) {
+        return name.to_string();
+    }
+
+    with_name_manager(|nm| nm.new_name(name))
+}
+
+/// Restarts the numbering of every kind of name.
+#[allow(dead_code)]
+pub fn reset_names() {
+    with_name_manager(|nm| nm.reset());
 }
@@ -1,43 +1,230 @@
-use ndarray::{array, Array2};
-use std::fmt::Debug;
+use ndarray::{array, ArcArray, Array, Array1, ArrayD, Axis, Dimension};
+use std::{any::Any, fmt::Debug};
 
-use crate::tensor::TensorRef;
+use crate::{
+    element::Element,
+    error::TensorError,
+    functions::Elementwise,
+    multi_output,
+    tensor::{GraphNode, MaybeSync, PendingGrads, TensorBuilder, TensorRef},
+    vmap,
+};
 
-pub trait Operation: Debug {
-    fn apply(&self, inputs: &[TensorRef]) -> TensorRef;
-    fn grad(&self, back_grad: TensorRef, args: &[TensorRef]) -> Vec<TensorRef>;
+pub trait Operation<T: Element = f64>: Any + Debug + MaybeSync {
+    /// Name of the operation, e.g. `"matmul"`, which its output tensors are also named after.
+    fn name(&self) -> &str;
+
+    /// Computes the output tensor, or reports why it can't be computed from `inputs`.
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError>;
+
+    /// Same as `try_apply`, panicking on error. Inside `vmap`, batched inputs go through
+    /// `apply_batched` instead.
+    fn apply(&self, inputs: &[TensorRef<T>]) -> TensorRef<T> {
+        vmap::apply(self, inputs).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Batching rule used by `vmap`: computes the output for a whole batch of examples, where the
+    /// `inputs` flagged in `batched` hold one example per entry of their first axis and the
+    /// others are shared by all examples. The output has the batch axis first.
+    ///
+    /// Like `grad`, it has to be computed with operations.
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        batched: &[bool],
+    ) -> Result<TensorRef<T>, TensorError>;
+
+    /// Gradients with respect to each of `args`, given the gradient of the output. `ctx` gives
+    /// access to the output itself and to the tensors saved while computing it, so that forward
+    /// work doesn't have to be redone.
+    ///
+    /// They have to be computed with operations rather than on raw arrays, so that the backward
+    /// pass itself can be recorded and differentiated when `create_graph` is set.
+    fn grad(
+        &self,
+        back_grad: TensorRef<T>,
+        args: &[TensorRef<T>],
+        ctx: &GradContext<T>,
+    ) -> Vec<TensorRef<T>>;
+
+    /// Tangent of the output, given the tangents of each of `args` (`None` where an argument has
+    /// none, i.e. a zero tangent). Returns `None` when the output doesn't depend on any tangent.
+    ///
+    /// Like `grad`, it has to be computed with operations.
+    fn jvp(&self, tangents: &[Option<TensorRef<T>>], args: &[TensorRef<T>])
+        -> Option<TensorRef<T>>;
+
+    /// Parents of another element type than the output, which the graph can't hold among the
+    /// arguments, like the source of a `Cast`. A backward pass orders them with the other
+    /// parents, so they receive their gradients from `foreign_grad` in the same pass.
+    fn foreign_parents(&self) -> Vec<Box<dyn GraphNode>> {
+        Vec::new()
+    }
+
+    /// Adds the gradients of the `foreign_parents` to `pending`, given the gradient of the
+    /// output. Like `grad`, they have to be computed with operations.
+    fn foreign_grad(&self, _back_grad: &TensorRef<T>, _pending: &mut PendingGrads) {}
+
+    /// Identifies what the operation computes: two nodes applying operations with the same
+    /// fingerprint to the same arguments hold the same value, and
+    /// `Trace::eliminate_common_subexpressions` keeps only one of them. It names the operation
+    /// and every setting that changes its result, e.g. `reshape [2, 3]`. `None` for operations
+    /// whose results can't be compared that way.
+    fn fingerprint(&self) -> Option<String>;
+
+    /// The elementwise operation this is, if `Fused` can compute it as one of its steps.
+    fn elementwise(&self) -> Option<Elementwise> {
+        None
+    }
 }
 
-pub trait ToArray2 {
-    fn to_array2(self) -> Array2<f64>;
+/// Operation computing several tensors at once, like the values and indices of `top_k!`.
+///
+/// It is recorded in the graph as a group of nodes, see `multi_output`. The gradient rule runs
+/// once per backward pass with the gradients of all the outputs.
+#[allow(dead_code)]
+pub trait MultiOperation<T: Element = f64>: Debug + MaybeSync {
+    /// Name the output tensors are given, e.g. `"top_k"`.
+    fn name(&self) -> &str;
+
+    /// Computes the values of the outputs, or reports why they can't be computed from `inputs`.
+    fn compute(&self, inputs: &[TensorRef<T>]) -> Result<Vec<ArrayD<T>>, TensorError>;
+
+    /// Computes the output tensors, recorded in the graph.
+    fn try_apply(&self, inputs: &[TensorRef<T>]) -> Result<Vec<TensorRef<T>>, TensorError>
+    where
+        Self: Clone + Sized + 'static,
+    {
+        multi_output::try_apply(self, inputs)
+    }
+
+    /// Same as `try_apply`, panicking on error. Inside `vmap`, batched inputs go through
+    /// `apply_batched` instead.
+    fn apply(&self, inputs: &[TensorRef<T>]) -> Vec<TensorRef<T>>
+    where
+        Self: Clone + Sized + 'static,
+    {
+        vmap::apply_multi(self, inputs).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Batching rule used by `vmap`, see `Operation::apply_batched`. Every output has the batch
+    /// axis first.
+    fn apply_batched(
+        &self,
+        inputs: &[TensorRef<T>],
+        batched: &[bool],
+    ) -> Result<Vec<TensorRef<T>>, TensorError>;
+
+    /// Gradients with respect to each of `args`, given the gradient of each of the `outputs`
+    /// (`None` where an output received none, e.g. because it wasn't used). Like
+    /// `Operation::grad`, it has to be computed with operations.
+    fn grad(
+        &self,
+        back_grads: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+        outputs: &[TensorRef<T>],
+    ) -> Vec<TensorRef<T>>;
+
+    /// Tangents of the `outputs`, given the tangents of each of `args`, see `Operation::jvp`.
+    fn jvp(
+        &self,
+        tangents: &[Option<TensorRef<T>>],
+        args: &[TensorRef<T>],
+        outputs: &[TensorRef<T>],
+    ) -> Vec<Option<TensorRef<T>>>;
+}
+
+/// What `Operation::grad` receives besides the gradient and the arguments: the output the
+/// gradient belongs to, and the tensors the operation saved with `TensorBuilder::saved` when it
+/// computed that output (masks, intermediate results, ...).
+pub struct GradContext<'a, T: Element> {
+    output: &'a TensorRef<T>,
+    saved: &'a [TensorRef<T>],
+}
+
+impl<'a, T: Element> GradContext<'a, T> {
+    pub fn new(output: &'a TensorRef<T>, saved: &'a [TensorRef<T>]) -> Self {
+        GradContext { output, saved }
+    }
+
+    /// The tensor computed by the operation. It is part of the graph, so gradients computed from
+    /// it can be differentiated again.
+    pub fn output(&self) -> &TensorRef<T> {
+        self.output
+    }
+
+    /// The tensors saved by the operation, in the order they were given.
+    pub fn saved(&self) -> &[TensorRef<T>] {
+        self.saved
+    }
+}
+
+/// The tangents of `args` for `Operation::jvp`, with zeros standing in for the missing ones, or
+/// `None` when none of them has a tangent.
+pub fn tangents_or_zeros<T: Element>(
+    tangents: &[Option<TensorRef<T>>],
+    args: &[TensorRef<T>],
+) -> Option<Vec<TensorRef<T>>> {
+    if tangents.iter().all(Option::is_none) {
+        return None;
+    }
+
+    let tangents = tangents
+        .iter()
+        .zip(args)
+        .map(|(tangent, arg)| match tangent {
+            Some(tangent) => tangent.clone(),
+            None => TensorRef::new(
+                TensorBuilder::new(ArrayD::<T>::zeros(arg.borrow().arr.raw_dim()))
+                    .requires_grad(false)
+                    .build(),
+            ),
+        })
+        .collect();
+
+    Some(tangents)
+}
+
+/// Conversion into the dynamic-rank array every tensor stores.
+///
+/// Plain numbers become 1x1 matrices and vectors become column vectors, which is what the 2-D
+/// code in the examples expects. Arrays keep their own rank, so a true scalar is `arr0(x)`.
+pub trait ToArray<T: Element> {
+    fn to_array(self) -> ArrayD<T>;
+}
+
+impl<T: Element> ToArray<T> for T {
+    fn to_array(self) -> ArrayD<T> {
+        array![[self]].into_dyn()
+    }
 }
 
-impl ToArray2 for f64 {
-    fn to_array2(self) -> Array2<f64> {
-        array![[self]]
+impl<T: Element> ToArray<T> for i32 {
+    fn to_array(self) -> ArrayD<T> {
+        array![[T::cast_from(self.into())]].into_dyn()
     }
 }
 
-impl ToArray2 for i32 {
-    fn to_array2(self) -> Array2<f64> {
-        array![[self.into()]]
+impl<T: Element> ToArray<T> for Vec<T> {
+    fn to_array(self) -> ArrayD<T> {
+        Array1::from(self).insert_axis(Axis(1)).into_dyn()
     }
 }
 
-impl ToArray2 for Vec<f64> {
-    fn to_array2(self) -> Array2<f64> {
-        Array2::from_shape_vec((self.len(), 1), self).expect("Invalid shape!")
+impl<T: Element> ToArray<T> for &[T] {
+    fn to_array(self) -> ArrayD<T> {
+        Array1::from(self.to_vec()).insert_axis(Axis(1)).into_dyn()
     }
 }
 
-impl ToArray2 for &[f64] {
-    fn to_array2(self) -> Array2<f64> {
-        Array2::from_shape_vec((self.len(), 1), self.to_vec()).expect("Invalid shape!")
+impl<T: Element, D: Dimension> ToArray<T> for Array<T, D> {
+    fn to_array(self) -> ArrayD<T> {
+        self.into_dyn()
     }
 }
 
-impl ToArray2 for Array2<f64> {
-    fn to_array2(self) -> Array2<f64> {
-        self
+impl<T: Element, D: Dimension> ToArray<T> for ArcArray<T, D> {
+    fn to_array(self) -> ArrayD<T> {
+        self.into_owned().into_dyn()
     }
 }
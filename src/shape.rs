@@ -0,0 +1,115 @@
+use ndarray::{ArrayD, Axis};
+
+use crate::{element::Element, error::TensorError};
+
+/// Shape of the result of an elementwise operation between arrays of shapes `a` and `b`,
+/// following the usual broadcasting rules: shapes are aligned on their trailing axes, and two
+/// axes are compatible when they are equal or one of them is 1. Missing leading axes count as 1.
+pub fn broadcast_shape(a: &[usize], b: &[usize]) -> Option<Vec<usize>> {
+    let rank = a.len().max(b.len());
+
+    (0..rank)
+        .map(|axis| {
+            let dim_a = axis_from_end(a, rank - 1 - axis);
+            let dim_b = axis_from_end(b, rank - 1 - axis);
+
+            match (dim_a, dim_b) {
+                (x, y) if x == y => Some(x),
+                (1, y) => Some(y),
+                (x, 1) => Some(x),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Checks that `a` and `b` can be combined elementwise, reporting both shapes otherwise.
+pub fn check_broadcastable(op: &str, a: &[usize], b: &[usize]) -> Result<(), TensorError> {
+    match broadcast_shape(a, b) {
+        Some(_) => Ok(()),
+        None => Err(TensorError::ShapeMismatch {
+            op: op.to_string(),
+            lhs: a.to_vec(),
+            rhs: b.to_vec(),
+        }),
+    }
+}
+
+/// Checks that `axis` is one of the axes of `shape`.
+pub fn check_axis(op: &str, shape: &[usize], axis: usize) -> Result<(), TensorError> {
+    if axis < shape.len() {
+        Ok(())
+    } else {
+        Err(TensorError::ShapeMismatch {
+            op: op.to_string(),
+            lhs: shape.to_vec(),
+            rhs: vec![axis],
+        })
+    }
+}
+
+/// Checks that an `index` array can pick positions along `axis` of an array of shape `shape`,
+/// like the index of `gather!`: it has as many axes, and along the other axes it's no longer.
+pub fn check_index_shape(
+    op: &str,
+    shape: &[usize],
+    index: &[usize],
+    axis: usize,
+) -> Result<(), TensorError> {
+    let fits = index.len() == shape.len()
+        && axis < shape.len()
+        && (0..shape.len()).all(|d| d == axis || index[d] <= shape[d]);
+    if fits {
+        Ok(())
+    } else {
+        Err(TensorError::ShapeMismatch {
+            op: op.to_string(),
+            lhs: shape.to_vec(),
+            rhs: index.to_vec(),
+        })
+    }
+}
+
+/// The position held by `value`, an index stored as an element like the indices returned by
+/// `top_k!`, along an axis of length `len`.
+pub fn position<T: Element>(op: &str, value: T, len: usize) -> Result<usize, TensorError> {
+    match value.to_usize() {
+        Some(index) if index < len && T::cast_from(index as f64) == value => Ok(index),
+        _ => Err(TensorError::IndexOutOfBounds {
+            op: op.to_string(),
+            index: value.to_i64().unwrap_or(i64::MIN),
+            len,
+        }),
+    }
+}
+
+/// Reduces a gradient computed for a broadcast result back to the `shape` of the operand that was
+/// broadcast: leading axes the operand didn't have are summed away, and so are the axes where the
+/// operand had size 1. A bias of shape (64, 1) added to a (64, 32) batch therefore receives the
+/// sum of the batch gradients over the second axis.
+pub fn unbroadcast<T: Element>(grad: ArrayD<T>, shape: &[usize]) -> ArrayD<T> {
+    if grad.shape() == shape {
+        return grad;
+    }
+
+    let mut grad = grad;
+    while grad.ndim() > shape.len() {
+        grad = grad.sum_axis(Axis(0));
+    }
+
+    for (axis, &dim) in shape.iter().enumerate() {
+        if dim == 1 && grad.shape()[axis] != 1 {
+            grad = grad.sum_axis(Axis(axis)).insert_axis(Axis(axis));
+        }
+    }
+
+    grad
+}
+
+fn axis_from_end(shape: &[usize], offset: usize) -> usize {
+    if offset < shape.len() {
+        shape[shape.len() - 1 - offset]
+    } else {
+        1
+    }
+}
@@ -0,0 +1,473 @@
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+};
+
+use crate::{add, element::Element, error::TensorError, tensor, tensor::TensorRef};
+
+/// Settings for a backward pass.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BackwardOptions {
+    /// Record the gradient computations as a graph of their own, so that the gradients stored on
+    /// the tensors can be back-propagated again to get second and higher-order derivatives.
+    pub create_graph: bool,
+    /// Keep the graph after the pass so it can be back-propagated again. Otherwise every operation
+    /// node the pass goes through drops its parents and operation, releasing the intermediate
+    /// tensors, and a later pass reaching it fails with `GraphFreed`. Creating a gradient graph
+    /// always retains the graph, as the gradients refer to it.
+    pub retain_graph: bool,
+}
+
+impl BackwardOptions {
+    pub fn retains_graph(&self) -> bool {
+        self.retain_graph || self.create_graph
+    }
+}
+
+/// A tensor of any element type, as a node of the graph a backward pass goes through. Casts
+/// connect graphs of different element types, so the pass can't be generic over one of them.
+pub trait GraphNode {
+    /// Same as `TensorRef::id`.
+    fn id(&self) -> usize;
+
+    /// The parents of the tensor, followed by the `Operation::foreign_parents` of its operation.
+    fn graph_parents(&self) -> Result<Vec<Box<dyn GraphNode>>, TensorError>;
+
+    /// Takes the gradient `pending` holds for the tensor, if any, accumulates it into the tensor
+    /// and hands the gradients of the parents back to `pending`.
+    fn backward_step(
+        &self,
+        pending: &mut PendingGrads,
+        options: BackwardOptions,
+    ) -> Result<(), TensorError>;
+
+    /// The `TensorRef` behind the node, to be downcast to its element type.
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// Returns every node reachable from `roots` ordered so that each node comes before all of its
+/// parents, i.e. the order in which gradients have to be processed during back-propagation.
+/// Parents of another element type are followed too, see `GraphNode`.
+///
+/// The depth-first search keeps an explicit stack, so arbitrarily deep graphs don't overflow the
+/// call stack, and each node is expanded once even when it is shared by several consumers. Fails
+/// when a node is mutably borrowed, as its parents can't be read.
+pub fn topological_order<T: Element>(
+    roots: &[TensorRef<T>],
+) -> Result<Vec<Box<dyn GraphNode>>, TensorError> {
+    let mut visited: HashSet<usize> = HashSet::new();
+    let mut order: Vec<Box<dyn GraphNode>> = Vec::new();
+    let mut stack: Vec<(Box<dyn GraphNode>, bool)> = roots
+        .iter()
+        .rev()
+        .map(|r| (Box::new(r.clone()) as Box<dyn GraphNode>, false))
+        .collect();
+
+    while let Some((node, expanded)) = stack.pop() {
+        if expanded {
+            order.push(node);
+            continue;
+        }
+
+        if !visited.insert(node.id()) {
+            continue;
+        }
+
+        let parents = node.graph_parents()?;
+        stack.push((node, true));
+        for parent in parents.into_iter().rev() {
+            if !visited.contains(&parent.id()) {
+                stack.push((parent, false));
+            }
+        }
+    }
+
+    order.reverse();
+    Ok(order)
+}
+
+/// Gradients flowing into nodes that haven't been processed yet, summed per node. When a
+/// gradient graph is being created the sums are recorded as operations too.
+///
+/// A pass can cross casts, so the nodes are of any element type: each gradient is stored as the
+/// `TensorRef` of its node's type.
+pub struct PendingGrads {
+    grads: HashMap<usize, Box<dyn Any>>,
+    create_graph: bool,
+    /// Gradients kept for the requested nodes when the pass is isolated, see `isolated`.
+    captured: Option<HashMap<usize, Option<Box<dyn Any>>>>,
+}
+
+impl PendingGrads {
+    pub fn new(create_graph: bool) -> Self {
+        PendingGrads {
+            grads: HashMap::new(),
+            create_graph,
+            captured: None,
+        }
+    }
+
+    /// Pending gradients of a pass that leaves `Tensor::grad` alone: the full gradients of
+    /// `inputs` are kept here instead, to be read back with `captured`.
+    pub fn isolated<T: Element>(create_graph: bool, inputs: &[TensorRef<T>]) -> Self {
+        PendingGrads {
+            captured: Some(inputs.iter().map(|input| (input.id(), None)).collect()),
+            ..PendingGrads::new(create_graph)
+        }
+    }
+
+    pub fn create_graph(&self) -> bool {
+        self.create_graph
+    }
+
+    pub fn is_isolated(&self) -> bool {
+        self.captured.is_some()
+    }
+
+    /// Keeps the gradient `node` received in an isolated pass, if it was requested.
+    pub fn capture<T: Element>(&mut self, node: &TensorRef<T>, grad: TensorRef<T>) {
+        if let Some(slot) = self
+            .captured
+            .as_mut()
+            .and_then(|captured| captured.get_mut(&node.id()))
+        {
+            *slot = Some(Box::new(grad));
+        }
+    }
+
+    /// The gradient captured for `node`, `None` when the pass didn't reach it.
+    pub fn captured<T: Element>(&self, node: &TensorRef<T>) -> Option<TensorRef<T>> {
+        let grad = self.captured.as_ref()?.get(&node.id())?.as_ref()?;
+
+        grad.downcast_ref::<TensorRef<T>>().cloned()
+    }
+
+    pub fn add<T: Element>(&mut self, node: &TensorRef<T>, grad: TensorRef<T>) {
+        let Some(existing) = self.grads.get_mut(&node.id()) else {
+            self.grads.insert(node.id(), Box::new(grad));
+            return;
+        };
+        let existing = existing
+            .downcast_mut::<TensorRef<T>>()
+            .expect("gradients have the element type of their node");
+
+        if self.create_graph {
+            *existing = add!(existing, grad);
+        } else {
+            let sum = &existing.borrow().arr + &grad.borrow().arr;
+            *existing = tensor!(sum);
+        }
+    }
+
+    pub fn take<T: Element>(&mut self, node: &TensorRef<T>) -> Option<TensorRef<T>> {
+        let grad = self.grads.remove(&node.id())?;
+        let grad = grad
+            .downcast::<TensorRef<T>>()
+            .expect("gradients have the element type of their node");
+
+        Some(*grad)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use crate::{
+        add,
+        error::TensorError,
+        prod, relu, stop_gradient, sub, sum, tanh, tensor,
+        tensor::{BackwardOptions, TensorRef},
+    };
+
+    fn grad_of(t: &TensorRef) -> f64 {
+        t.borrow().grad().expect("tensor has no gradient").arr[[0, 0]]
+    }
+
+    #[test]
+    fn add_diamond_sums_both_paths() {
+        let x = tensor!(3.0);
+        let y = add!(x, x);
+        let z = add!(y, y);
+
+        z.backward(None);
+
+        assert_eq!(grad_of(&x), 4.0);
+    }
+
+    #[test]
+    fn sub_diamond_cancels_paths() {
+        let x = tensor!(3.0);
+        let y = sub!(x, x);
+        let z = sub!(prod!(x, 3.0), x);
+
+        y.backward(None);
+        assert_eq!(grad_of(&x), 0.0);
+
+        x.zero_grad();
+        z.backward(None);
+        assert_eq!(grad_of(&x), 2.0);
+    }
+
+    #[test]
+    fn prod_diamond_applies_product_rule() {
+        let x = tensor!(3.0);
+        let a = add!(x, 1.0);
+        let b = prod!(x, 2.0);
+        let z = prod!(a, b);
+
+        z.backward(None);
+
+        // d/dx (x + 1) * 2x = 4x + 2
+        assert_eq!(grad_of(&x), 14.0);
+    }
+
+    #[test]
+    fn shared_intermediate_is_visited_once() {
+        let x = tensor!(3.0);
+        let y = prod!(x, 2.0);
+        let z = add!(prod!(y, y), y);
+
+        z.backward(None);
+
+        // d/dx (4x^2 + 2x) = 8x + 2
+        assert_eq!(grad_of(&x), 26.0);
+        assert_eq!(grad_of(&y), 13.0);
+    }
+
+    #[test]
+    fn sibling_leaves_own_their_gradients() {
+        let a = tensor!(1.0);
+        let b = tensor!(2.0);
+        let c = add!(a, b);
+
+        c.backward(None);
+
+        let a_grad = a.borrow().grad.clone().unwrap();
+        let b_grad = b.borrow().grad.clone().unwrap();
+        assert_ne!(a_grad.id(), b_grad.id());
+
+        a.zero_grad();
+        assert_eq!(grad_of(&a), 0.0);
+        assert_eq!(grad_of(&b), 1.0);
+    }
+
+    #[test]
+    fn deep_chain_does_not_overflow_the_stack() {
+        let x = tensor!(1.0);
+        let mut y = x.clone();
+        for _ in 0..100_000 {
+            y = add!(y, 1.0);
+        }
+
+        y.backward(None);
+
+        assert_eq!(grad_of(&x), 1.0);
+    }
+
+    #[test]
+    fn seed_gradient_is_copied() {
+        let x = tensor!(3.0);
+        let y = sub!(x, 1.0);
+        let seed = tensor!(5.0);
+
+        y.backward(Some(seed.clone()));
+        seed.borrow_mut().arr.fill(100.0);
+
+        assert_eq!(grad_of(&y), 5.0);
+        assert_eq!(grad_of(&x), 5.0);
+        assert_ne!(y.borrow().grad.clone().unwrap().id(), seed.id());
+    }
+
+    #[test]
+    fn gradients_accumulate_until_zeroed() {
+        let x = tensor!(3.0);
+
+        prod!(x, x).backward(None);
+        prod!(x, x).backward(None);
+        assert_eq!(grad_of(&x), 12.0);
+
+        x.zero_grad();
+        prod!(x, x).backward(None);
+        assert_eq!(grad_of(&x), 6.0);
+    }
+
+    #[test]
+    fn create_graph_allows_second_derivatives() {
+        let x = tensor!(2.0);
+        let y = prod!(prod!(x, x), x);
+
+        let options = BackwardOptions {
+            create_graph: true,
+            ..Default::default()
+        };
+        y.backward_with(None, options);
+        let dy_dx = x.borrow_mut().grad.take().unwrap();
+        assert_eq!(dy_dx.borrow().arr[[0, 0]], 12.0);
+
+        dy_dx.backward(None);
+        assert_eq!(grad_of(&x), 12.0);
+    }
+
+    #[test]
+    fn non_scalar_seed_is_an_error() {
+        let x = tensor!(array![[1.0, 2.0]]);
+        let y = prod!(x, x);
+
+        assert_eq!(
+            y.try_backward(None),
+            Err(TensorError::NonScalarSeed { shape: vec![1, 2] })
+        );
+        assert!(x.borrow().grad.is_none());
+    }
+
+    #[test]
+    fn borrowed_node_is_a_conflict() {
+        let x = tensor!(3.0);
+        let y = prod!(x, x);
+
+        let _x_borrow = x.borrow_mut();
+        assert_eq!(y.try_backward(None), Err(TensorError::BorrowConflict));
+    }
+
+    #[test]
+    fn graph_is_freed_after_backward() {
+        let x = tensor!(3.0);
+        let y = prod!(x, x);
+
+        y.backward(None);
+        assert!(y.borrow().parents.is_empty());
+
+        let name = y.borrow().name.clone();
+        assert_eq!(y.try_backward(None), Err(TensorError::GraphFreed { name }));
+        assert_eq!(grad_of(&x), 6.0);
+    }
+
+    #[test]
+    fn retained_graph_can_be_backpropagated_again() {
+        let x = tensor!(3.0);
+        let y = add!(prod!(x, x), x);
+        let options = BackwardOptions {
+            retain_graph: true,
+            ..Default::default()
+        };
+
+        y.backward_with(None, options);
+        y.backward(None);
+        assert_eq!(grad_of(&x), 14.0);
+
+        assert!(matches!(
+            y.try_backward(None),
+            Err(TensorError::GraphFreed { .. })
+        ));
+    }
+
+    #[test]
+    fn stop_gradient_blocks_one_path() {
+        let x = tensor!(3.0);
+        let y = prod!(x, stop_gradient!(x));
+
+        y.backward(None);
+
+        // x is treated as a constant on the second path.
+        assert_eq!(y.borrow().arr[[0, 0]], 9.0);
+        assert_eq!(grad_of(&x), 3.0);
+    }
+
+    #[test]
+    fn detached_tensor_has_no_history() {
+        let x = tensor!(3.0);
+        let y = prod!(x, x);
+        let y_detached = y.detach();
+
+        assert!(y_detached.borrow().parents.is_empty());
+        assert!(!y_detached.borrow().requires_grad);
+
+        prod!(y_detached, x).backward(None);
+        assert_eq!(grad_of(&x), 9.0);
+
+        // The values are shared until one of the tensors is modified.
+        assert!(std::ptr::eq(
+            y.borrow().arr.as_ptr(),
+            y_detached.borrow().arr.as_ptr()
+        ));
+        y_detached.borrow_mut().arr.fill(0.0);
+        assert_eq!(y.borrow().arr[[0, 0]], 9.0);
+    }
+
+    #[test]
+    fn hook_replaces_intermediate_gradient() {
+        let x = tensor!(3.0);
+        let y = prod!(x, 2.0);
+        let z = prod!(y, y);
+
+        // dz/dy = 2y = 12, halved before reaching y and x.
+        y.register_hook(|g| Some(prod!(g, 0.5)));
+        z.backward(None);
+
+        assert_eq!(grad_of(&y), 6.0);
+        assert_eq!(grad_of(&x), 12.0);
+    }
+
+    #[test]
+    fn hooks_see_leaf_gradients_until_removed() {
+        let x = tensor!(3.0);
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let recorded = seen.clone();
+        let handle = x.register_hook(move |g| {
+            recorded.lock().unwrap().push(g.borrow().arr[[0, 0]]);
+            None
+        });
+        prod!(x, x).backward(None);
+
+        assert!(x.remove_hook(handle));
+        prod!(x, 5.0).backward(None);
+
+        assert_eq!(*seen.lock().unwrap(), vec![6.0]);
+        assert_eq!(grad_of(&x), 11.0);
+    }
+
+    #[test]
+    fn gradients_from_the_output_can_be_differentiated() {
+        let x = tensor!(0.5);
+        let y = tanh!(x);
+
+        let options = BackwardOptions {
+            create_graph: true,
+            ..Default::default()
+        };
+        y.backward_with(None, options);
+        let dy_dx = x.borrow_mut().grad.take().unwrap();
+        dy_dx.backward(None);
+
+        // d/dx (1 - tanh(x)^2) = -2 tanh(x) (1 - tanh(x)^2)
+        let t = 0.5_f64.tanh();
+        assert!((dy_dx.borrow().arr[[0, 0]] - (1.0 - t * t)).abs() < 1e-12);
+        assert!((grad_of(&x) + 2.0 * t * (1.0 - t * t)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn relu_gradient_uses_the_saved_mask() {
+        let x = tensor!(array![[-1.0, 2.0]]);
+        let y = relu!(x);
+
+        sum!(y).backward(None);
+
+        assert_eq!(
+            x.borrow().grad().unwrap().arr,
+            array![[0.0, 1.0]].into_dyn()
+        );
+        assert!(y.borrow().is_graph_freed());
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn graph_built_on_one_thread_backpropagates_on_another() {
+        let x = tensor!(3.0);
+        let y = prod!(x, x);
+
+        std::thread::spawn(move || y.backward(None)).join().unwrap();
+        assert_eq!(grad_of(&x), 6.0);
+    }
+}
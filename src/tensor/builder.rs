@@ -1,129 +1,423 @@
-use ndarray::Array2;
-use std::{
-    cell::{Ref, RefCell, RefMut},
-    rc::Rc,
-};
+use std::any::Any;
+
+use ndarray::{ArcArray, ArrayD, IxDyn};
 
 use crate::{
-    operation::{Operation, ToArray2},
+    add,
+    element::Element,
+    error::TensorError,
+    forward::{is_forward_mode, output_tangent},
+    grad_mode::{is_grad_enabled, GradModeGuard},
+    operation::{GradContext, Operation, ToArray},
     tensor,
+    tensor::{
+        topological_order, BackwardOptions, GradHook, GraphNode, HookFn, HookHandle, Hooks,
+        PendingGrads, ReadGuard, Shared, WriteGuard,
+    },
+    trace,
 };
 
+/// Shared handle to a tensor: `Rc<RefCell<_>>` by default, `Arc<RwLock<_>>` with the `sync`
+/// feature (see `Shared`).
 #[derive(Debug, Clone)]
-pub struct TensorRef(Rc<RefCell<Tensor>>);
+pub struct TensorRef<T: Element = f64>(Shared<Tensor<T>>);
 
-impl TensorRef {
-    pub fn new(tensor: Tensor) -> Self {
-        TensorRef(Rc::new(RefCell::new(tensor)))
+impl<T: Element> TensorRef<T> {
+    pub fn new(tensor: Tensor<T>) -> Self {
+        let tensor = TensorRef(Shared::new(tensor));
+        trace::record_created(&tensor);
+        tensor
     }
 
-    pub fn borrow(&self) -> Ref<'_, Tensor> {
-        self.0.borrow()
+    pub fn borrow(&self) -> ReadGuard<'_, Tensor<T>> {
+        self.0.read()
     }
 
-    pub fn borrow_mut(&self) -> RefMut<'_, Tensor> {
-        self.0.borrow_mut()
+    pub fn borrow_mut(&self) -> WriteGuard<'_, Tensor<T>> {
+        self.0.write()
     }
 
-    pub fn try_borrow(&self) -> Result<Ref<'_, Tensor>, std::cell::BorrowError> {
-        self.0.try_borrow()
+    /// Like `borrow`, failing with `BorrowConflict` when the tensor is currently borrowed mutably.
+    pub fn try_borrow(&self) -> Result<ReadGuard<'_, Tensor<T>>, TensorError> {
+        self.0.try_read().ok_or(TensorError::BorrowConflict)
     }
 
-    pub fn try_borrow_mut(&self) -> Result<RefMut<'_, Tensor>, std::cell::BorrowMutError> {
-        self.0.try_borrow_mut()
+    /// Like `borrow_mut`, failing with `BorrowConflict` when the tensor is currently borrowed.
+    pub fn try_borrow_mut(&self) -> Result<WriteGuard<'_, Tensor<T>>, TensorError> {
+        self.0.try_write().ok_or(TensorError::BorrowConflict)
     }
 
-    pub fn try_unwrap(self) -> Result<Tensor, Self> {
-        match Rc::try_unwrap(self.0) {
-            Ok(ref_cell) => Ok(ref_cell.into_inner()),
-            Err(rc) => Err(TensorRef(rc)),
-        }
+    pub fn try_unwrap(self) -> Result<Tensor<T>, Self> {
+        self.0.try_unwrap().map_err(TensorRef)
+    }
+
+    /// Registers `hook` to run on this tensor's gradient whenever a backward pass computes it,
+    /// for leaves as well as intermediate results. The hook receives the gradient summed over
+    /// every consumer of the tensor and can return a replacement, e.g. a clipped or scaled
+    /// gradient, which is stored and back-propagated instead. Hooks run while the tensor is
+    /// borrowed by the backward pass, so they must not borrow the tensor itself.
+    pub fn register_hook(&self, hook: impl HookFn<T> + 'static) -> HookHandle {
+        let hook: GradHook<T> = Box::new(hook);
+        self.borrow_mut().hooks.register(hook)
+    }
+
+    /// Removes a hook added by `register_hook`, returning whether it was still registered.
+    pub fn remove_hook(&self, handle: HookHandle) -> bool {
+        self.borrow_mut().hooks.remove(handle)
+    }
+
+    /// New tensor holding the same values without any history: it has no parents or operation
+    /// and doesn't require a gradient, so nothing computed from it back-propagates into this
+    /// tensor's graph. The values aren't copied, both tensors share their storage (see
+    /// `Tensor::arr`). Use `stop_gradient!` to block gradients while keeping the tensor in the
+    /// graph.
+    pub fn detach(&self) -> Self {
+        let arr = self.borrow().arr.clone();
+        TensorRef::new(TensorBuilder::shared(arr).requires_grad(false).build())
+    }
+
+    /// Identity of the underlying node, stable for as long as any handle to it is alive.
+    pub fn id(&self) -> usize {
+        self.0.as_ptr() as usize
     }
 
     pub fn zero_grad(&self) {
         self.borrow_mut().zero_grad();
     }
 
+    /// Back-propagates `grad` (or ones when `None`) through the graph rooted at this tensor.
+    ///
+    /// The graph is ordered topologically once, so every node has received the gradients of all
+    /// its consumers before its own contribution is forwarded to its parents. The traversal is
+    /// iterative and visits each node exactly once, no matter how many paths lead to it. The graph
+    /// is freed along the way unless `retain_graph` is set, see `BackwardOptions`.
+    ///
+    /// Panics where `try_backward` would return an error.
     pub fn backward(&self, grad: Option<Self>) {
-        self.borrow_mut().backward(grad);
+        self.backward_with(grad, BackwardOptions::default());
+    }
+
+    /// Same as `backward`, with the behaviour of the pass controlled by `options`.
+    pub fn backward_with(&self, grad: Option<Self>, options: BackwardOptions) {
+        self.try_backward_with(grad, options)
+            .unwrap_or_else(|err| panic!("{err}"));
+    }
+
+    /// Same as `backward`, reporting a missing seed gradient on a tensor with several values, a
+    /// seed of the wrong shape or a node of the graph that is borrowed elsewhere as an error.
+    ///
+    /// The seed is checked before anything is back-propagated. A borrow conflict is only found
+    /// when the pass reaches the node, so the gradients of the nodes before it are already updated.
+    pub fn try_backward(&self, grad: Option<Self>) -> Result<(), TensorError> {
+        self.try_backward_with(grad, BackwardOptions::default())
+    }
+
+    /// Same as `try_backward`, with the behaviour of the pass controlled by `options`.
+    pub fn try_backward_with(
+        &self,
+        grad: Option<Self>,
+        options: BackwardOptions,
+    ) -> Result<(), TensorError> {
+        let mut pending = PendingGrads::new(options.create_graph);
+        self.run_backward(grad, &mut pending, options)
+    }
+
+    /// Back-propagates like `try_backward_with`, but leaves the `grad` of every tensor unchanged
+    /// and returns the gradients that reach each of `inputs` instead, `None` for those that
+    /// don't receive any. Hooks still run on the way.
+    pub fn try_grads_of(
+        &self,
+        grad: Option<Self>,
+        inputs: &[Self],
+        options: BackwardOptions,
+    ) -> Result<Vec<Option<Self>>, TensorError> {
+        let mut pending = PendingGrads::isolated(options.create_graph, inputs);
+        self.run_backward(grad, &mut pending, options)?;
+
+        Ok(inputs.iter().map(|input| pending.captured(input)).collect())
+    }
+
+    fn run_backward(
+        &self,
+        grad: Option<Self>,
+        pending: &mut PendingGrads,
+        options: BackwardOptions,
+    ) -> Result<(), TensorError> {
+        let Some(seed) = self.try_borrow()?.seed(grad)? else {
+            return Ok(());
+        };
+
+        // The gradient computations are only recorded when a gradient graph is requested.
+        let _grad_mode = GradModeGuard::new(options.create_graph);
+
+        let order = topological_order(std::slice::from_ref(self))?;
+        pending.add(self, seed);
+
+        for node in order {
+            node.backward_step(pending, options)?;
+        }
+
+        Ok(())
+    }
+
+    fn accumulate_and_propagate(
+        &self,
+        my_grad: Self,
+        pending: &mut PendingGrads,
+        options: BackwardOptions,
+    ) -> Result<(), TensorError> {
+        let mut tensor = self.try_borrow_mut()?;
+        if !tensor.requires_grad {
+            return Ok(());
+        }
+        tensor.check_graph()?;
+
+        let my_grad = if tensor.hooks.is_empty() {
+            my_grad
+        } else {
+            tensor.hooks.run(my_grad)?
+        };
+        if pending.is_isolated() {
+            pending.capture(self, Tensor::owned_grad(&my_grad, pending.create_graph())?);
+        } else {
+            tensor.accumulate_grad(&my_grad, pending.create_graph())?;
+        }
+
+        let Some(operation) = tensor.operation.take() else {
+            return Ok(());
+        };
+        let parents = tensor.parents.clone();
+        let saved = tensor.saved.clone();
+        drop(tensor);
+
+        // The node isn't borrowed while its gradient rule runs, so the rule can use the output.
+        let ctx = GradContext::new(self, &saved);
+        let parent_grads = operation.grad(my_grad.clone(), &parents, &ctx);
+        for (parent, parent_grad) in parents.iter().zip(parent_grads) {
+            pending.add(parent, parent_grad);
+        }
+        operation.foreign_grad(&my_grad, pending);
+
+        let mut tensor = self.try_borrow_mut()?;
+        if options.retains_graph() {
+            tensor.operation = Some(operation);
+        } else {
+            tensor.free_graph();
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: Element> GraphNode for TensorRef<T> {
+    fn id(&self) -> usize {
+        TensorRef::id(self)
+    }
+
+    fn graph_parents(&self) -> Result<Vec<Box<dyn GraphNode>>, TensorError> {
+        let tensor = self.try_borrow()?;
+        let mut parents: Vec<Box<dyn GraphNode>> = tensor
+            .parents
+            .iter()
+            .map(|parent| Box::new(parent.clone()) as Box<dyn GraphNode>)
+            .collect();
+        if let Some(operation) = &tensor.operation {
+            parents.extend(operation.foreign_parents());
+        }
+
+        Ok(parents)
+    }
+
+    fn backward_step(
+        &self,
+        pending: &mut PendingGrads,
+        options: BackwardOptions,
+    ) -> Result<(), TensorError> {
+        match pending.take(self) {
+            Some(grad) => self.accumulate_and_propagate(grad, pending, options),
+            None => Ok(()),
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
     }
 }
 
 #[derive(Debug)]
-pub struct Tensor {
-    pub arr: Array2<f64>,
-    pub parents: Vec<TensorRef>,
+pub struct Tensor<T: Element = f64> {
+    /// The values, in storage shared with the tensors `detach` returns. It's copied when modified
+    /// while shared, so each tensor only ever sees its own changes.
+    pub arr: ArcArray<T, IxDyn>,
+    pub parents: Vec<TensorRef<T>>,
     pub requires_grad: bool,
-    #[allow(dead_code)]
     pub name: Option<String>,
-    pub operation: Option<Box<dyn Operation>>,
-    pub grad: Option<TensorRef>,
+    pub operation: Option<Box<dyn Operation<T>>>,
+    /// Gradient accumulated by `backward`. Successive calls add into it until `zero_grad` resets it.
+    ///
+    /// A plain backward pass stores a buffer owned by this tensor alone: it's never shared with
+    /// the gradient handed in by the caller, with an operation's output or with any other
+    /// tensor's gradient, so it's updated in place. With `create_graph` the gradient is a node of
+    /// the gradient graph instead; such a gradient is never modified in place, accumulating into
+    /// it or zeroing it replaces it with a new tensor.
+    pub grad: Option<TensorRef<T>>,
+    /// Directional derivative of `arr` computed in forward mode, see `forward::jvp`.
+    pub tangent: Option<TensorRef<T>>,
+    /// Tensors the operation saved for its gradient rule, see `GradContext`.
+    saved: Vec<TensorRef<T>>,
+    /// Set once a backward pass without `retain_graph` released the parents and operation.
+    graph_freed: bool,
+    hooks: Hooks<T>,
 }
 
-impl Tensor {
-    pub fn backward(&mut self, my_grad_input: Option<TensorRef>) {
+impl<T: Element> Tensor<T> {
+    /// The gradient a backward pass from this tensor starts with, or `None` when it doesn't
+    /// require a gradient.
+    fn seed(&self, grad: Option<TensorRef<T>>) -> Result<Option<TensorRef<T>>, TensorError> {
         if !self.requires_grad {
-            return;
+            return Ok(None);
         }
+        self.check_graph()?;
 
-        let my_grad: TensorRef = if let Some(g) = my_grad_input {
-            g
-        } else {
-            tensor!(Array2::ones(self.arr.raw_dim()))
+        match grad {
+            Some(g) => {
+                let grad_shape = g.try_borrow()?.arr.shape().to_vec();
+                if grad_shape != self.arr.shape() {
+                    return Err(TensorError::ShapeMismatch {
+                        op: "backward".to_string(),
+                        lhs: self.arr.shape().to_vec(),
+                        rhs: grad_shape,
+                    });
+                }
+                Ok(Some(g))
+            }
+            None if self.arr.len() != 1 => Err(TensorError::NonScalarSeed {
+                shape: self.arr.shape().to_vec(),
+            }),
+            None => Ok(Some(tensor!(ArrayD::ones(self.arr.raw_dim())))),
+        }
+    }
+
+    fn accumulate_grad(
+        &mut self,
+        my_grad: &TensorRef<T>,
+        create_graph: bool,
+    ) -> Result<(), TensorError> {
+        let Some(existing_grad) = &self.grad else {
+            self.grad = Some(Tensor::owned_grad(my_grad, create_graph)?);
+            return Ok(());
         };
 
-        if self.grad.is_none() {
-            self.grad = Some(my_grad.clone());
+        if create_graph {
+            self.grad = Some(add!(existing_grad, my_grad));
+        } else if existing_grad.try_borrow()?.is_leaf() {
+            existing_grad.try_borrow_mut()?.arr += &my_grad.try_borrow()?.arr;
         } else {
-            let mut existing_grad_tensor = self.grad.as_ref().unwrap().borrow_mut();
-            existing_grad_tensor.arr += &my_grad.borrow().arr;
+            let sum = &existing_grad.try_borrow()?.arr + &my_grad.try_borrow()?.arr;
+            self.grad = Some(tensor!(sum));
         }
 
-        if let Some(operation) = &self.operation {
-            let parent_grads = operation.grad(my_grad, &self.parents);
+        Ok(())
+    }
 
-            for (parent, parent_grad) in self.parents.iter().zip(parent_grads) {
-                parent.borrow_mut().backward(Some(parent_grad));
-            }
+    /// `grad` as a gradient to be stored: a buffer of its own in a plain pass, see `Tensor::grad`.
+    fn owned_grad(grad: &TensorRef<T>, create_graph: bool) -> Result<TensorRef<T>, TensorError> {
+        if create_graph && !grad.try_borrow()?.is_leaf() {
+            Ok(grad.clone())
+        } else {
+            Ok(tensor!(grad.try_borrow()?.arr.clone()))
         }
     }
 
-    pub fn grad(&self) -> Option<Ref<'_, Tensor>> {
+    /// Releases the parents, operation and saved tensors of a computed tensor once its gradient
+    /// was propagated.
+    fn free_graph(&mut self) {
+        self.operation = None;
+        self.parents.clear();
+        self.saved.clear();
+        self.graph_freed = true;
+    }
+
+    /// Takes the operation out of a computed tensor, freeing its graph as a backward pass would.
+    pub fn take_operation(&mut self) -> Option<Box<dyn Operation<T>>> {
+        let operation = self.operation.take()?;
+        self.free_graph();
+        Some(operation)
+    }
+
+    fn check_graph(&self) -> Result<(), TensorError> {
+        if self.graph_freed {
+            return Err(TensorError::GraphFreed {
+                name: self.name.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether this tensor was created directly rather than computed by an operation. A tensor
+    /// whose graph was freed isn't a leaf, even though it no longer holds its operation.
+    pub fn is_leaf(&self) -> bool {
+        self.operation.is_none() && !self.graph_freed
+    }
+
+    pub fn is_graph_freed(&self) -> bool {
+        self.graph_freed
+    }
+
+    pub fn grad(&self) -> Option<ReadGuard<'_, Tensor<T>>> {
         self.grad.as_ref().map(|g| g.borrow())
     }
 
-    pub fn grad_mut(&self) -> Option<RefMut<'_, Tensor>> {
+    /// Like `grad`, failing with `MissingGradient` when no gradient was received yet.
+    pub fn try_grad(&self) -> Result<ReadGuard<'_, Tensor<T>>, TensorError> {
+        self.grad
+            .as_ref()
+            .ok_or_else(|| TensorError::MissingGradient {
+                name: self.name.clone(),
+            })?
+            .try_borrow()
+    }
+
+    pub fn grad_mut(&self) -> Option<WriteGuard<'_, Tensor<T>>> {
         self.grad.as_ref().map(|g| g.borrow_mut())
     }
 
     pub fn zero_grad(&mut self) {
-        if let Some(grad_rc) = &self.grad {
+        if let Some(grad_rc) = self.grad.as_ref().filter(|g| g.borrow().is_leaf()) {
             let mut grad_borrow = grad_rc.borrow_mut();
-            grad_borrow.arr.fill(0.0);
+            grad_borrow.arr.fill(T::zero());
         } else {
-            let zeros_arr: Array2<f64> = Array2::from_elem(self.arr.raw_dim(), 0.0);
+            let zeros_arr: ArrayD<T> = ArrayD::zeros(self.arr.raw_dim());
             self.grad = Some(tensor!(zeros_arr));
         }
     }
 
-    pub fn set_arr(&mut self, val: impl ToArray2) {
-        self.arr = val.to_array2();
+    pub fn set_arr(&mut self, val: impl ToArray<T>) {
+        self.arr = val.to_array().into_shared();
     }
 }
 
-pub struct TensorBuilder {
+pub struct TensorBuilder<T: Element = f64> {
     name: Option<String>,
-    operation: Option<Box<dyn Operation>>,
-    arr: Array2<f64>,
-    parents: Vec<TensorRef>,
+    operation: Option<Box<dyn Operation<T>>>,
+    arr: ArcArray<T, IxDyn>,
+    parents: Vec<TensorRef<T>>,
+    saved: Vec<TensorRef<T>>,
     requires_grad: bool,
 }
 
-impl TensorBuilder {
-    pub fn new<T: ToArray2>(arr: T) -> Self {
+impl<T: Element> TensorBuilder<T> {
+    pub fn new<A: ToArray<T>>(arr: A) -> Self {
+        Self::shared(arr.to_array().into_shared())
+    }
+
+    /// Builds a tensor on `arr` without copying it, sharing the storage with the other tensors
+    /// holding it.
+    pub fn shared(arr: ArcArray<T, IxDyn>) -> Self {
         Self {
-            arr: arr.to_array2(),
+            arr,
             parents: Vec::new(),
+            saved: Vec::new(),
             requires_grad: true,
             name: None,
             operation: None,
@@ -135,19 +429,34 @@ impl TensorBuilder {
         self
     }
 
-    pub fn parents(mut self, parents: Vec<TensorRef>) -> Self {
+    /// Records the tensors this one is computed from. Left out of the tensor while gradients
+    /// are disabled.
+    pub fn parents(mut self, parents: Vec<TensorRef<T>>) -> Self {
         self.parents = parents;
         self
     }
 
-    pub fn operation(mut self, operation: Box<dyn Operation>) -> Self {
+    /// Records the operation that computed this tensor. While gradients are disabled the result
+    /// is left out of the graph instead and doesn't require a gradient.
+    pub fn operation(mut self, operation: Box<dyn Operation<T>>) -> Self {
+        if !is_grad_enabled() {
+            self.requires_grad = false;
+        }
         self.operation = Some(operation);
         self
     }
 
+    /// Saves tensors computed along with this one for the gradient rule of its operation, which
+    /// receives them through `GradContext::saved`. Dropped with the parents while gradients are
+    /// disabled, and released with them when the graph is freed.
+    pub fn saved(mut self, saved: Vec<TensorRef<T>>) -> Self {
+        self.saved = saved;
+        self
+    }
+
     #[allow(dead_code)]
-    pub fn arr<T: ToArray2>(mut self, arr: T) -> Self {
-        self.arr = arr.to_array2();
+    pub fn arr<A: ToArray<T>>(mut self, arr: A) -> Self {
+        self.arr = arr.to_array().into_shared();
         self
     }
 
@@ -156,19 +465,39 @@ impl TensorBuilder {
         self
     }
 
-    pub fn build(self) -> Tensor {
+    /// Builds the tensor, computing its tangent first in forward mode. The parents and operation
+    /// are still needed for the tangent even while gradients are disabled, so they're only
+    /// dropped here.
+    pub fn build(self) -> Tensor<T> {
+        let tangent = match &self.operation {
+            Some(operation) if is_forward_mode() => {
+                output_tangent(operation.as_ref(), &self.parents)
+            }
+            _ => None,
+        };
+
+        let (parents, operation, saved) = if is_grad_enabled() {
+            (self.parents, self.operation, self.saved)
+        } else {
+            (Vec::new(), None, Vec::new())
+        };
+
         Tensor {
             arr: self.arr,
-            parents: self.parents,
+            parents,
             requires_grad: self.requires_grad,
             name: self.name,
-            operation: self.operation,
+            operation,
             grad: None,
+            tangent,
+            saved,
+            graph_freed: false,
+            hooks: Hooks::new(),
         }
     }
 }
 
-impl std::fmt::Display for Tensor {
+impl<T: Element> std::fmt::Display for Tensor<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "{}", self.arr)
     }
@@ -0,0 +1,84 @@
+use std::fmt;
+
+use crate::{
+    element::Element,
+    error::TensorError,
+    tensor::{MaybeSync, TensorRef},
+};
+
+/// Closure run on the gradient of a tensor during back-propagation. Returning `Some` replaces
+/// the gradient, both the one stored on the tensor and the one passed on to its parents.
+pub trait HookFn<T: Element>: FnMut(&TensorRef<T>) -> Option<TensorRef<T>> + MaybeSync {}
+
+impl<T: Element, F> HookFn<T> for F where F: FnMut(&TensorRef<T>) -> Option<TensorRef<T>> + MaybeSync
+{}
+
+pub type GradHook<T> = Box<dyn HookFn<T>>;
+
+/// Identifies a hook registered with `TensorRef::register_hook`, to remove it later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HookHandle(usize);
+
+/// Gradient hooks of a tensor, run in registration order.
+pub struct Hooks<T: Element> {
+    hooks: Vec<(HookHandle, GradHook<T>)>,
+    next_id: usize,
+}
+
+impl<T: Element> Hooks<T> {
+    pub fn new() -> Self {
+        Hooks {
+            hooks: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn register(&mut self, hook: GradHook<T>) -> HookHandle {
+        let handle = HookHandle(self.next_id);
+        self.next_id += 1;
+        self.hooks.push((handle, hook));
+        handle
+    }
+
+    /// Returns whether a hook was registered under `handle`.
+    pub fn remove(&mut self, handle: HookHandle) -> bool {
+        let len = self.hooks.len();
+        self.hooks.retain(|(h, _)| *h != handle);
+        self.hooks.len() != len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Passes `grad` through every hook, each one seeing the result of the previous ones. A
+    /// replacement gradient must keep the shape of the one it replaces.
+    pub fn run(&mut self, grad: TensorRef<T>) -> Result<TensorRef<T>, TensorError> {
+        let mut grad = grad;
+
+        for (_, hook) in self.hooks.iter_mut() {
+            let Some(new_grad) = hook(&grad) else {
+                continue;
+            };
+
+            let old_shape = grad.try_borrow()?.arr.shape().to_vec();
+            let new_shape = new_grad.try_borrow()?.arr.shape().to_vec();
+            if old_shape != new_shape {
+                return Err(TensorError::ShapeMismatch {
+                    op: "hook".to_string(),
+                    lhs: old_shape,
+                    rhs: new_shape,
+                });
+            }
+            grad = new_grad;
+        }
+
+        Ok(grad)
+    }
+}
+
+impl<T: Element> fmt::Debug for Hooks<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Hooks({})", self.hooks.len())
+    }
+}
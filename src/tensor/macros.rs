@@ -1,34 +1,67 @@
+use ndarray::{ArcArray, Array, Dimension};
+
 use crate::{
-    operation::ToArray2,
+    element::Element,
     tensor::{Tensor, TensorBuilder, TensorRef},
 };
 
-impl ToTensor for Tensor {
-    fn to_tensor(self) -> TensorRef {
+impl<T: Element> ToTensor<T> for Tensor<T> {
+    fn to_tensor(self) -> TensorRef<T> {
         TensorRef::new(self)
     }
 }
 
-pub trait ToTensor {
-    fn to_tensor(self) -> TensorRef;
+pub trait ToTensor<T: Element> {
+    fn to_tensor(self) -> TensorRef<T>;
 }
 
-impl ToTensor for TensorRef {
-    fn to_tensor(self) -> TensorRef {
+impl<T: Element> ToTensor<T> for TensorRef<T> {
+    fn to_tensor(self) -> TensorRef<T> {
         self
     }
 }
 
-impl<'a> ToTensor for &'a TensorRef {
-    fn to_tensor(self) -> TensorRef {
+impl<T: Element> ToTensor<T> for &TensorRef<T> {
+    fn to_tensor(self) -> TensorRef<T> {
         self.clone()
     }
 }
 
-impl<T: ToArray2> ToTensor for T {
-    fn to_tensor(self) -> TensorRef {
-        let tensor = TensorBuilder::new(self.to_array2()).build();
-        TensorRef::new(tensor)
+// One impl per `ToArray` source rather than a blanket one: with `ToArray` generic over the
+// element type, a blanket impl would overlap with the `Tensor` and `TensorRef` impls above.
+impl<T: Element> ToTensor<T> for T {
+    fn to_tensor(self) -> TensorRef<T> {
+        TensorRef::new(TensorBuilder::new(self).build())
+    }
+}
+
+impl<T: Element> ToTensor<T> for i32 {
+    fn to_tensor(self) -> TensorRef<T> {
+        TensorRef::new(TensorBuilder::new(self).build())
+    }
+}
+
+impl<T: Element> ToTensor<T> for Vec<T> {
+    fn to_tensor(self) -> TensorRef<T> {
+        TensorRef::new(TensorBuilder::new(self).build())
+    }
+}
+
+impl<T: Element> ToTensor<T> for &[T] {
+    fn to_tensor(self) -> TensorRef<T> {
+        TensorRef::new(TensorBuilder::new(self).build())
+    }
+}
+
+impl<T: Element, D: Dimension> ToTensor<T> for Array<T, D> {
+    fn to_tensor(self) -> TensorRef<T> {
+        TensorRef::new(TensorBuilder::new(self).build())
+    }
+}
+
+impl<T: Element, D: Dimension> ToTensor<T> for ArcArray<T, D> {
+    fn to_tensor(self) -> TensorRef<T> {
+        TensorRef::new(TensorBuilder::shared(self.into_dyn()).build())
     }
 }
 
@@ -40,7 +73,7 @@ macro_rules! tensor {
         $val.to_tensor()
     }};
 
-    ($val:expr $(, name: $name:expr)? $(, requires_grad: $grad:expr)? $(, parents: $parents:expr)? $(, operation: $operation:expr)?) => {{
+    ($val:expr $(, name: $name:expr)? $(, requires_grad: $grad:expr)? $(, parents: $parents:expr)? $(, operation: $operation:expr)? $(, saved: $saved:expr)?) => {{
         use $crate::tensor::TensorRef;
 
         let mut builder = TensorBuilder::new($val);
@@ -56,6 +89,9 @@ macro_rules! tensor {
         $(
             builder = builder.operation($operation);
         )?
+        $(
+            builder = builder.saved($saved);
+        )?
         TensorRef::new(builder.build())
     }};
 }
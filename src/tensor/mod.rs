@@ -1,7 +1,14 @@
+mod backward;
 #[allow(dead_code)]
 mod builder;
+mod hooks;
 #[allow(dead_code)]
 mod macros;
+mod ops;
+mod shared;
 
+pub use backward::*;
 pub use builder::*;
+pub use hooks::*;
 pub use macros::*;
+pub use shared::*;
@@ -0,0 +1,181 @@
+//! Arithmetic operators on tensors, so that `w.matmul(&x) + &b` builds the same graph as
+//! `add!(matmul!(w, x), b)`.
+//!
+//! Tensors can be combined with other tensors, taken by value or by reference, with plain numbers
+//! and with arrays. Numbers and arrays become constants that don't require a gradient; a number
+//! is a 0-d array, so it broadcasts to the shape of the tensor without changing it.
+
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use ndarray::{arr0, Array, Dimension};
+
+use crate::{
+    add, div,
+    element::Element,
+    matmul, prod, sub, tensor,
+    tensor::{TensorBuilder, TensorRef},
+};
+
+impl<T: Element> TensorRef<T> {
+    /// Matrix product of this tensor with `other`, same as `matmul!(self, other)`.
+    pub fn matmul(&self, other: &TensorRef<T>) -> TensorRef<T> {
+        matmul!(self, other)
+    }
+}
+
+fn constant<T: Element, D: Dimension>(arr: Array<T, D>) -> TensorRef<T> {
+    TensorRef::new(TensorBuilder::new(arr).requires_grad(false).build())
+}
+
+macro_rules! impl_binary_op {
+    ($op_trait:ident, $op_fn:ident, $assign_trait:ident, $assign_fn:ident, $op_macro:ident) => {
+        impl<T: Element> $op_trait<TensorRef<T>> for TensorRef<T> {
+            type Output = TensorRef<T>;
+
+            fn $op_fn(self, rhs: TensorRef<T>) -> TensorRef<T> {
+                $op_macro!(self, rhs)
+            }
+        }
+
+        impl<T: Element> $op_trait<&TensorRef<T>> for TensorRef<T> {
+            type Output = TensorRef<T>;
+
+            fn $op_fn(self, rhs: &TensorRef<T>) -> TensorRef<T> {
+                $op_macro!(self, rhs)
+            }
+        }
+
+        impl<T: Element> $op_trait<TensorRef<T>> for &TensorRef<T> {
+            type Output = TensorRef<T>;
+
+            fn $op_fn(self, rhs: TensorRef<T>) -> TensorRef<T> {
+                $op_macro!(self, rhs)
+            }
+        }
+
+        impl<T: Element> $op_trait<&TensorRef<T>> for &TensorRef<T> {
+            type Output = TensorRef<T>;
+
+            fn $op_fn(self, rhs: &TensorRef<T>) -> TensorRef<T> {
+                $op_macro!(self, rhs)
+            }
+        }
+
+        impl<T: Element> $op_trait<T> for TensorRef<T> {
+            type Output = TensorRef<T>;
+
+            fn $op_fn(self, rhs: T) -> TensorRef<T> {
+                $op_macro!(self, constant(arr0(rhs)))
+            }
+        }
+
+        impl<T: Element> $op_trait<T> for &TensorRef<T> {
+            type Output = TensorRef<T>;
+
+            fn $op_fn(self, rhs: T) -> TensorRef<T> {
+                $op_macro!(self, constant(arr0(rhs)))
+            }
+        }
+
+        impl<T: Element, D: Dimension> $op_trait<Array<T, D>> for TensorRef<T> {
+            type Output = TensorRef<T>;
+
+            fn $op_fn(self, rhs: Array<T, D>) -> TensorRef<T> {
+                $op_macro!(self, constant(rhs))
+            }
+        }
+
+        impl<T: Element, D: Dimension> $op_trait<Array<T, D>> for &TensorRef<T> {
+            type Output = TensorRef<T>;
+
+            fn $op_fn(self, rhs: Array<T, D>) -> TensorRef<T> {
+                $op_macro!(self, constant(rhs))
+            }
+        }
+
+        impl_binary_op!(@scalar_lhs f32, $op_trait, $op_fn, $op_macro);
+        impl_binary_op!(@scalar_lhs f64, $op_trait, $op_fn, $op_macro);
+
+        /// Rebinds the handle to the result, leaving the tensor it pointed to untouched: it may
+        /// still be needed by the graph, so `x += y` records a new node just like `x = x + y`.
+        impl<T: Element, Rhs> $assign_trait<Rhs> for TensorRef<T>
+        where
+            TensorRef<T>: $op_trait<Rhs, Output = TensorRef<T>>,
+        {
+            fn $assign_fn(&mut self, rhs: Rhs) {
+                *self = self.clone().$op_fn(rhs);
+            }
+        }
+    };
+
+    (@scalar_lhs $scalar:ty, $op_trait:ident, $op_fn:ident, $op_macro:ident) => {
+        impl $op_trait<TensorRef<$scalar>> for $scalar {
+            type Output = TensorRef<$scalar>;
+
+            fn $op_fn(self, rhs: TensorRef<$scalar>) -> TensorRef<$scalar> {
+                $op_macro!(constant(arr0(self)), rhs)
+            }
+        }
+
+        impl $op_trait<&TensorRef<$scalar>> for $scalar {
+            type Output = TensorRef<$scalar>;
+
+            fn $op_fn(self, rhs: &TensorRef<$scalar>) -> TensorRef<$scalar> {
+                $op_macro!(constant(arr0(self)), rhs)
+            }
+        }
+    };
+}
+
+impl_binary_op!(Add, add, AddAssign, add_assign, add);
+impl_binary_op!(Sub, sub, SubAssign, sub_assign, sub);
+impl_binary_op!(Mul, mul, MulAssign, mul_assign, prod);
+impl_binary_op!(Div, div, DivAssign, div_assign, div);
+
+impl<T: Element> Neg for TensorRef<T> {
+    type Output = TensorRef<T>;
+
+    fn neg(self) -> TensorRef<T> {
+        prod!(self, constant(arr0(-T::one())))
+    }
+}
+
+impl<T: Element> Neg for &TensorRef<T> {
+    type Output = TensorRef<T>;
+
+    fn neg(self) -> TensorRef<T> {
+        prod!(self, constant(arr0(-T::one())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use crate::tensor;
+
+    #[test]
+    fn operators_record_gradients() {
+        let x = tensor!(3.0);
+        let y = tensor!(2.0);
+
+        // z = (x * y - x) / y + 1 - (-x)
+        let z = (&x * &y - &x) / &y + 1.0 - (-&x);
+        z.backward(None);
+
+        let grad_of = |t: &crate::tensor::TensorRef| t.borrow().grad().unwrap().arr[[0, 0]];
+        assert_eq!(z.borrow().arr[[0, 0]], 5.5);
+        assert_eq!(grad_of(&x), 1.5);
+        assert_eq!(grad_of(&y), 0.75);
+    }
+
+    #[test]
+    fn scalar_operands_keep_the_tensor_shape() {
+        let mut x = tensor!(array![1.0, 2.0]);
+        x *= 2.0;
+        x += array![1.0, 1.0];
+
+        assert_eq!(x.borrow().arr, array![3.0, 5.0].into_dyn());
+        assert_eq!((2.0_f64 * &x).borrow().arr.shape(), &[2]);
+    }
+}
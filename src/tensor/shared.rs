@@ -0,0 +1,120 @@
+//! Storage behind `TensorRef`.
+//!
+//! By default tensors live in an `Rc<RefCell<_>>`, which is cheap but confines a graph to the
+//! thread that built it. With the `sync` cargo feature they live in an `Arc<RwLock<_>>` instead
+//! and tensors, graphs and trained parameters become `Send + Sync`. Operations are written
+//! against the aliases and methods below, so they work unchanged with either storage.
+
+#[cfg(not(feature = "sync"))]
+mod storage {
+    use std::{
+        cell::{Ref, RefCell, RefMut},
+        rc::Rc,
+    };
+
+    pub type ReadGuard<'a, T> = Ref<'a, T>;
+    pub type WriteGuard<'a, T> = RefMut<'a, T>;
+
+    #[derive(Debug)]
+    pub struct Shared<T>(Rc<RefCell<T>>);
+
+    impl<T> Shared<T> {
+        pub fn new(value: T) -> Self {
+            Shared(Rc::new(RefCell::new(value)))
+        }
+
+        pub fn read(&self) -> ReadGuard<'_, T> {
+            self.0.borrow()
+        }
+
+        pub fn write(&self) -> WriteGuard<'_, T> {
+            self.0.borrow_mut()
+        }
+
+        pub fn try_read(&self) -> Option<ReadGuard<'_, T>> {
+            self.0.try_borrow().ok()
+        }
+
+        pub fn try_write(&self) -> Option<WriteGuard<'_, T>> {
+            self.0.try_borrow_mut().ok()
+        }
+
+        pub fn as_ptr(&self) -> *const () {
+            Rc::as_ptr(&self.0) as *const ()
+        }
+
+        pub fn try_unwrap(self) -> Result<T, Self> {
+            Rc::try_unwrap(self.0)
+                .map(RefCell::into_inner)
+                .map_err(Shared)
+        }
+    }
+
+    impl<T> Clone for Shared<T> {
+        fn clone(&self) -> Self {
+            Shared(self.0.clone())
+        }
+    }
+
+    /// Bound added to everything stored inside a tensor: nothing by default.
+    pub trait MaybeSync {}
+
+    impl<T: ?Sized> MaybeSync for T {}
+}
+
+#[cfg(feature = "sync")]
+mod storage {
+    use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+    pub type ReadGuard<'a, T> = RwLockReadGuard<'a, T>;
+    pub type WriteGuard<'a, T> = RwLockWriteGuard<'a, T>;
+
+    #[derive(Debug)]
+    pub struct Shared<T>(Arc<RwLock<T>>);
+
+    // A panic while a tensor was locked doesn't make it unusable, just as with `RefCell`.
+    impl<T> Shared<T> {
+        pub fn new(value: T) -> Self {
+            Shared(Arc::new(RwLock::new(value)))
+        }
+
+        pub fn read(&self) -> ReadGuard<'_, T> {
+            self.0.read().unwrap_or_else(|e| e.into_inner())
+        }
+
+        pub fn write(&self) -> WriteGuard<'_, T> {
+            self.0.write().unwrap_or_else(|e| e.into_inner())
+        }
+
+        pub fn try_read(&self) -> Option<ReadGuard<'_, T>> {
+            self.0.try_read().ok()
+        }
+
+        pub fn try_write(&self) -> Option<WriteGuard<'_, T>> {
+            self.0.try_write().ok()
+        }
+
+        pub fn as_ptr(&self) -> *const () {
+            Arc::as_ptr(&self.0) as *const ()
+        }
+
+        pub fn try_unwrap(self) -> Result<T, Self> {
+            Arc::try_unwrap(self.0)
+                .map(|lock| lock.into_inner().unwrap_or_else(|e| e.into_inner()))
+                .map_err(Shared)
+        }
+    }
+
+    impl<T> Clone for Shared<T> {
+        fn clone(&self) -> Self {
+            Shared(self.0.clone())
+        }
+    }
+
+    /// Bound added to everything stored inside a tensor: `Send + Sync` so graphs can be shared.
+    pub trait MaybeSync: Send + Sync {}
+
+    impl<T: ?Sized + Send + Sync> MaybeSync for T {}
+}
+
+pub use storage::*;
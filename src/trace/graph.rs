@@ -0,0 +1,298 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+};
+
+use crate::{
+    element::Element, error::TensorError, name_manager::PausedNamesGuard, operation::Operation,
+    tensor::TensorRef,
+};
+
+thread_local! {
+    /// Ids of the tensors created while `trace` runs on this thread.
+    static CREATED: RefCell<Option<HashSet<usize>>> = const { RefCell::new(None) };
+}
+
+/// Notes that `tensor` was created, if a trace is running on this thread.
+pub fn record_created<T: Element>(tensor: &TensorRef<T>) {
+    CREATED.with(|c| {
+        if let Some(created) = c.borrow_mut().as_mut() {
+            created.insert(tensor.id());
+        }
+    });
+}
+
+/// Collects the created tensors until dropped. A trace started inside another one passes the
+/// tensors it saw on to the outer trace.
+struct TracingGuard {
+    previous: Option<HashSet<usize>>,
+}
+
+impl TracingGuard {
+    fn new() -> Self {
+        let previous = CREATED.with(|c| c.replace(Some(HashSet::new())));
+        TracingGuard { previous }
+    }
+
+    fn created(&self) -> HashSet<usize> {
+        CREATED.with(|c| c.borrow().clone().unwrap_or_default())
+    }
+}
+
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        let mut previous = self.previous.take();
+        let created = CREATED.with(|c| c.replace(None));
+        if let (Some(previous), Some(created)) = (previous.as_mut(), created) {
+            previous.extend(created);
+        }
+        CREATED.with(|c| c.replace(previous));
+    }
+}
+
+#[derive(Debug)]
+pub(super) enum Node<T: Element> {
+    /// The input at this position of the arguments of `Trace::run`.
+    Input { index: usize, shape: Vec<usize> },
+    /// A tensor the function captured, used as is.
+    Captured(TensorRef<T>),
+    /// A tensor the function created without an operation, e.g. a number it multiplies by.
+    /// Unlike captured tensors, nothing outside the trace can update it.
+    Constant(TensorRef<T>),
+    /// An operation applied to earlier nodes.
+    Op {
+        operation: Box<dyn Operation<T>>,
+        args: Vec<usize>,
+        name: Option<String>,
+    },
+}
+
+/// A function recorded by `trace`, which can be replayed on new inputs of the same shapes.
+///
+/// Captured tensors are read when the trace runs, so updates to parameters are picked up.
+/// Control flow isn't recorded: every run applies the operations of the traced run.
+#[derive(Debug)]
+pub struct Trace<T: Element = f64> {
+    pub(super) nodes: Vec<Node<T>>,
+}
+
+/// Runs `f` on `inputs` and records the graph it computes as a `Trace`, panicking where
+/// `try_trace` would return an error.
+pub fn trace<T, F>(f: F, inputs: &[TensorRef<T>]) -> Trace<T>
+where
+    T: Element,
+    F: FnOnce(&[TensorRef<T>]) -> TensorRef<T>,
+{
+    try_trace(f, inputs).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// Runs `f` on `inputs` and records the graph it computes as a `Trace`.
+///
+/// The operations are taken out of the graph built by this run, which is freed like after a
+/// backward pass: tensors from it that `f` kept elsewhere can't be back-propagated.
+///
+/// A trace only holds tensors of one element type, so `f` can cast tensors it captured but not
+/// ones it computed: a `cast!` of those is reported as `TensorError::Untraceable`.
+pub fn try_trace<T, F>(f: F, inputs: &[TensorRef<T>]) -> Result<Trace<T>, TensorError>
+where
+    T: Element,
+    F: FnOnce(&[TensorRef<T>]) -> TensorRef<T>,
+{
+    let (output, created) = {
+        let tracing = TracingGuard::new();
+        let output = f(inputs);
+        (output, tracing.created())
+    };
+
+    let input_slots: HashMap<usize, usize> = inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| (input.id(), index))
+        .collect();
+
+    let mut nodes = Vec::new();
+    let mut index_of: HashMap<usize, usize> = HashMap::new();
+    let mut visited: HashSet<usize> = HashSet::new();
+    let mut stack = vec![(output, false)];
+
+    while let Some((tensor, expanded)) = stack.pop() {
+        let id = tensor.id();
+        if !expanded && !visited.insert(id) {
+            continue;
+        }
+
+        let node = if let Some(&index) = input_slots.get(&id) {
+            let shape = tensor.borrow().arr.shape().to_vec();
+            Node::Input { index, shape }
+        } else if !created.contains(&id) {
+            Node::Captured(tensor)
+        } else if tensor.borrow().operation.is_none() {
+            Node::Constant(tensor)
+        } else if !expanded {
+            stack.push((tensor.clone(), true));
+            for parent in tensor.borrow().parents.iter().rev() {
+                if !visited.contains(&parent.id()) {
+                    stack.push((parent.clone(), false));
+                }
+            }
+            continue;
+        } else {
+            let mut tensor = tensor.borrow_mut();
+            let operation = tensor
+                .operation
+                .as_ref()
+                .expect("traced node has an operation");
+            let foreign_parents = operation.foreign_parents();
+            if foreign_parents.iter().any(|p| created.contains(&p.id())) {
+                return Err(TensorError::Untraceable {
+                    op: operation.name().to_string(),
+                });
+            }
+
+            let args = tensor.parents.iter().map(|p| index_of[&p.id()]).collect();
+            let name = tensor.name.clone();
+            let operation = tensor
+                .take_operation()
+                .expect("traced node has an operation");
+            Node::Op {
+                operation,
+                args,
+                name,
+            }
+        };
+
+        index_of.insert(id, nodes.len());
+        nodes.push(node);
+    }
+
+    Ok(Trace { nodes })
+}
+
+impl<T: Element> Trace<T> {
+    /// Number of operations applied by each run.
+    pub fn num_operations(&self) -> usize {
+        self.nodes
+            .iter()
+            .filter(|node| matches!(node, Node::Op { .. }))
+            .count()
+    }
+
+    /// Replays the trace on `inputs`, panicking where `try_run` would return an error.
+    pub fn run(&self, inputs: &[TensorRef<T>]) -> TensorRef<T> {
+        self.try_run(inputs).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Replays the trace on `inputs`, which must have the shapes of the traced inputs, and
+    /// returns the output. Its graph is recorded as usual, and its tensors get the names of the
+    /// traced ones.
+    pub fn try_run(&self, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError> {
+        let _names = PausedNamesGuard::new();
+        let mut values: Vec<TensorRef<T>> = Vec::with_capacity(self.nodes.len());
+
+        for node in &self.nodes {
+            let value = match node {
+                Node::Input { index, shape } => {
+                    let input = inputs[*index].clone();
+                    let input_shape = input.try_borrow()?.arr.shape().to_vec();
+                    if &input_shape != shape {
+                        return Err(TensorError::ShapeMismatch {
+                            op: "trace".to_string(),
+                            lhs: shape.clone(),
+                            rhs: input_shape,
+                        });
+                    }
+                    input
+                }
+                Node::Captured(tensor) | Node::Constant(tensor) => tensor.clone(),
+                Node::Op {
+                    operation,
+                    args,
+                    name,
+                } => {
+                    let args: Vec<TensorRef<T>> = args.iter().map(|&i| values[i].clone()).collect();
+                    let output = operation.try_apply(&args)?;
+                    output.try_borrow_mut()?.name = name.clone();
+                    output
+                }
+            };
+            values.push(value);
+        }
+
+        Ok(values.pop().expect("a trace has an output"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use crate::{
+        add, cast, error::TensorError, matmul, prod, sigmoid, sum, tensor, tensor::TensorRef, top_k,
+    };
+
+    use super::{trace, try_trace};
+
+    #[test]
+    fn replay_matches_eager_run() {
+        let w = tensor!(array![[0.5, -1.0], [2.0, 0.25]]);
+        let f = |v: &[TensorRef]| sum!(sigmoid!(add!(matmul!(w, v[0]), v[0])));
+
+        let traced = trace(f, &[tensor!(array![[0.0], [0.0]])]);
+        assert_eq!(traced.num_operations(), 4);
+
+        let x = tensor!(array![[1.0], [-2.0]]);
+        let replayed = traced.run(std::slice::from_ref(&x));
+        replayed.backward(None);
+        let replayed_grad = w.borrow().grad().unwrap().arr.clone();
+
+        w.zero_grad();
+        let eager = f(&[x]);
+        eager.backward(None);
+
+        assert_eq!(replayed.borrow().arr, eager.borrow().arr);
+        assert_eq!(replayed_grad, w.borrow().grad().unwrap().arr);
+    }
+
+    #[test]
+    fn replay_reads_captured_tensors_and_multiple_outputs() {
+        let scale = tensor!(array![[1.0, 2.0, 3.0]]);
+        let traced = trace(
+            |v: &[TensorRef]| top_k!(prod!(v[0], scale), 2).0,
+            &[tensor!(array![[0.0, 0.0, 0.0]])],
+        );
+
+        scale.borrow_mut().set_arr(array![[3.0, 2.0, 1.0]]);
+        let out = traced.run(&[tensor!(array![[1.0, 1.0, 1.0]])]);
+
+        assert_eq!(out.borrow().arr, array![[3.0, 2.0]].into_dyn());
+    }
+
+    #[test]
+    fn casts_of_computed_tensors_are_rejected() {
+        let result = try_trace(
+            |v: &[TensorRef]| sum!(cast!(cast!(prod!(v[0], 2.0), f32), f64)),
+            &[tensor!(1.0)],
+        );
+
+        assert_eq!(
+            result.unwrap_err(),
+            TensorError::Untraceable {
+                op: "cast".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn replay_reads_captured_tensors_through_casts() {
+        let scale = tensor!(2.0_f32);
+        let traced = trace(
+            |v: &[TensorRef]| sum!(prod!(v[0], cast!(scale, f64))),
+            &[tensor!(1.0)],
+        );
+
+        scale.borrow_mut().set_arr(3.0_f32);
+        let out = traced.run(&[tensor!(10.0)]);
+
+        assert_eq!(out.borrow().arr[[0, 0]], 30.0);
+    }
+}
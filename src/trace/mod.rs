@@ -0,0 +1,15 @@
+//! Graph tracing and replay.
+//!
+//! `trace` runs a function once and turns the graph it records into a `Trace`: a static list of
+//! nodes, each one an input slot, a tensor the function captured (parameters), a constant it
+//! created, or an operation with the nodes it's applied to. Replaying the trace on new inputs
+//! applies the recorded operations directly, without running the function, dispatching through
+//! the macros or handing out new names, and records a graph that back-propagates like any other.
+//!
+//! Since the nodes are known before any run, `Trace::optimize` can simplify them once for all
+//! the runs: see `passes`.
+
+mod graph;
+mod passes;
+
+pub use graph::*;
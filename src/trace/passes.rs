@@ -0,0 +1,295 @@
+//! Optimization passes over the nodes of a `Trace`.
+//!
+//! Each pass rewrites the nodes in place and then drops the ones the output no longer depends
+//! on. Replaying the optimized trace computes the same values and gradients as the traced run.
+
+use std::collections::HashMap;
+
+use crate::{
+    element::Element,
+    functions::{Fused, Operand, Step},
+    grad_mode::GradModeGuard,
+    name_manager::PausedNamesGuard,
+};
+
+use super::graph::{Node, Trace};
+
+/// Elementwise nodes merged into one `Fused` operation, reading the nodes listed in `inputs`.
+#[derive(Default)]
+struct Chain {
+    steps: Vec<Step>,
+    inputs: Vec<usize>,
+}
+
+impl Chain {
+    fn input(&mut self, node: usize) -> Operand {
+        let position = self.inputs.iter().position(|&input| input == node);
+        Operand::Input(position.unwrap_or_else(|| {
+            self.inputs.push(node);
+            self.inputs.len() - 1
+        }))
+    }
+
+    /// Appends the steps of `other`, returning the operand reading its result.
+    fn append(&mut self, other: Chain) -> Operand {
+        let Chain { steps, inputs } = other;
+        let offset = self.steps.len();
+
+        for step in steps {
+            let operands = step
+                .operands
+                .iter()
+                .map(|operand| match *operand {
+                    Operand::Input(index) => self.input(inputs[index]),
+                    Operand::Step(index) => Operand::Step(offset + index),
+                })
+                .collect();
+            self.steps.push(Step {
+                op: step.op,
+                operands,
+            });
+        }
+
+        Operand::Step(self.steps.len() - 1)
+    }
+}
+
+impl<T: Element> Trace<T> {
+    /// Runs all the passes: `fold_constants`, `eliminate_common_subexpressions` and
+    /// `fuse_elementwise`.
+    pub fn optimize(&mut self) {
+        self.fold_constants();
+        self.eliminate_common_subexpressions();
+        self.fuse_elementwise();
+    }
+
+    /// Computes the operations applied only to constants once, turning them into constants.
+    pub fn fold_constants(&mut self) {
+        let _no_grad = GradModeGuard::no_grad();
+        let _names = PausedNamesGuard::new();
+
+        for index in 0..self.nodes.len() {
+            let Node::Op {
+                operation,
+                args,
+                name,
+            } = &self.nodes[index]
+            else {
+                continue;
+            };
+
+            let constants: Option<Vec<_>> = args
+                .iter()
+                .map(|&arg| match &self.nodes[arg] {
+                    Node::Constant(tensor) => Some(tensor.clone()),
+                    _ => None,
+                })
+                .collect();
+            // Operations without arguments, like casts, read tensors the trace doesn't hold.
+            let Some(constants) = constants.filter(|constants| !constants.is_empty()) else {
+                continue;
+            };
+            // Left in place, the node reports the error when the trace runs.
+            let Ok(value) = operation.try_apply(&constants) else {
+                continue;
+            };
+
+            {
+                let mut value = value.borrow_mut();
+                value.name = name.clone();
+                value.requires_grad = false;
+            }
+            self.nodes[index] = Node::Constant(value);
+        }
+
+        self.compact(&identity(self.nodes.len()));
+    }
+
+    /// Keeps a single node of each group of nodes holding the same value: operations with the
+    /// same `Operation::fingerprint` applied to the same nodes, and equal constants.
+    pub fn eliminate_common_subexpressions(&mut self) {
+        let mut replacement: Vec<usize> = Vec::with_capacity(self.nodes.len());
+        let mut operations: HashMap<(String, Vec<usize>), usize> = HashMap::new();
+        let mut constants: Vec<usize> = Vec::new();
+
+        for index in 0..self.nodes.len() {
+            if let Node::Op { args, .. } = &mut self.nodes[index] {
+                for arg in args.iter_mut() {
+                    *arg = replacement[*arg];
+                }
+            }
+
+            let representative = match &self.nodes[index] {
+                Node::Op {
+                    operation, args, ..
+                } => match operation.fingerprint() {
+                    Some(fingerprint) => *operations
+                        .entry((fingerprint, args.clone()))
+                        .or_insert(index),
+                    None => index,
+                },
+                Node::Constant(tensor) => {
+                    let equal = constants.iter().copied().find(|&constant| {
+                        let Node::Constant(other) = &self.nodes[constant] else {
+                            unreachable!("only constants are listed");
+                        };
+                        other.borrow().arr == tensor.borrow().arr
+                    });
+                    equal.unwrap_or_else(|| {
+                        constants.push(index);
+                        index
+                    })
+                }
+                _ => index,
+            };
+            replacement.push(representative);
+        }
+
+        self.compact(&replacement);
+    }
+
+    /// Merges chains of elementwise operations into `Fused` operations, so that each chain is
+    /// computed in one pass over the values instead of one pass per operation. An intermediate
+    /// result is only merged into the operation using it when nothing else uses it.
+    pub fn fuse_elementwise(&mut self) {
+        let mut uses = vec![0; self.nodes.len()];
+        for node in &self.nodes {
+            if let Node::Op { args, .. } = node {
+                for &arg in args {
+                    uses[arg] += 1;
+                }
+            }
+        }
+
+        let mut chains: Vec<Option<Chain>> = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let chain = match node {
+                Node::Op {
+                    operation, args, ..
+                } => operation.elementwise().map(|op| {
+                    let mut chain = Chain::default();
+                    let operands = args
+                        .iter()
+                        .map(|&arg| match chains[arg].take_if(|_| uses[arg] == 1) {
+                            Some(inlined) => chain.append(inlined),
+                            None => chain.input(arg),
+                        })
+                        .collect();
+                    chain.steps.push(Step { op, operands });
+                    chain
+                }),
+                _ => None,
+            };
+            chains.push(chain);
+        }
+
+        // The nodes merged into another one are no longer used, `compact` drops them.
+        for (node, chain) in self.nodes.iter_mut().zip(chains) {
+            let Some(chain) = chain.filter(|chain| chain.steps.len() > 1) else {
+                continue;
+            };
+            let Node::Op { name, .. } = node else {
+                continue;
+            };
+
+            let name = name.take();
+            *node = Node::Op {
+                operation: Box::new(Fused::new(chain.steps)),
+                args: chain.inputs,
+                name,
+            };
+        }
+
+        self.compact(&identity(self.nodes.len()));
+    }
+
+    /// Drops the nodes the output doesn't depend on, after replacing each node `i` by
+    /// `replacement[i]`, either `i` itself or an earlier node holding the same value.
+    fn compact(&mut self, replacement: &[usize]) {
+        let output = replacement[self.nodes.len() - 1];
+        let mut needed = vec![false; self.nodes.len()];
+        needed[output] = true;
+        for index in (0..=output).rev() {
+            if let (true, Node::Op { args, .. }) = (needed[index], &self.nodes[index]) {
+                for &arg in args {
+                    needed[replacement[arg]] = true;
+                }
+            }
+        }
+
+        let mut new_index = vec![0; self.nodes.len()];
+        let mut nodes = Vec::new();
+        for (index, mut node) in std::mem::take(&mut self.nodes).into_iter().enumerate() {
+            if !needed[index] {
+                continue;
+            }
+            if let Node::Op { args, .. } = &mut node {
+                for arg in args.iter_mut() {
+                    *arg = new_index[replacement[*arg]];
+                }
+            }
+            new_index[index] = nodes.len();
+            nodes.push(node);
+        }
+
+        self.nodes = nodes;
+    }
+}
+
+fn identity(len: usize) -> Vec<usize> {
+    (0..len).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, ArrayBase, Data, IxDyn};
+
+    use crate::{
+        add, exp, ln, matmul, prod, sigmoid, sum, tensor, tensor::TensorRef, trace::trace,
+    };
+
+    fn assert_close(
+        actual: &ArrayBase<impl Data<Elem = f64>, IxDyn>,
+        expected: &ArrayBase<impl Data<Elem = f64>, IxDyn>,
+    ) {
+        assert_eq!(actual.shape(), expected.shape());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-12, "{actual} != {expected}");
+        }
+    }
+
+    #[test]
+    fn optimized_trace_matches_eager_run() {
+        let w = tensor!(array![[0.5, -1.0], [2.0, 0.25]]);
+        let b = tensor!(array![[0.1], [-0.2]]);
+        let f = |v: &[TensorRef]| {
+            let two = exp!(ln!(2.0));
+            let h1 = sigmoid!(add!(matmul!(w, v[0]), b));
+            let h2 = sigmoid!(add!(matmul!(w, v[0]), b));
+            sum!(prod!(add!(h1, h2), two))
+        };
+
+        let mut traced = trace(f, &[tensor!(array![[0.0], [0.0]])]);
+        assert_eq!(traced.num_operations(), 11);
+        traced.optimize();
+        // matmul, fused add and sigmoid, fused add and prod, sum.
+        assert_eq!(traced.num_operations(), 4);
+
+        let x = tensor!(array![[1.0], [-2.0]]);
+        let replayed = traced.run(std::slice::from_ref(&x));
+        replayed.backward(None);
+        let replayed_grads = (
+            w.borrow().grad().unwrap().arr.clone(),
+            b.borrow().grad().unwrap().arr.clone(),
+        );
+
+        w.zero_grad();
+        b.zero_grad();
+        let eager = f(&[x]);
+        eager.backward(None);
+
+        assert_close(&replayed.borrow().arr, &eager.borrow().arr);
+        assert_close(&replayed_grads.0, &w.borrow().grad().unwrap().arr);
+        assert_close(&replayed_grads.1, &b.borrow().grad().unwrap().arr);
+    }
+}
@@ -0,0 +1,397 @@
+//! Automatic batching.
+//!
+//! `vmap` runs a function written for a single example once on whole batches. The tensors passed
+//! to it are marked as batched, with their first axis as the batch axis, and every operation
+//! applied to a batched tensor while `f` runs goes through its `Operation::apply_batched` rule
+//! instead of `Operation::try_apply`. A rule builds the result for the whole batch, again with
+//! the batch axis first, out of ordinary operations, so the batched graph back-propagates like
+//! any other. Tensors `f` captures, like model parameters, are shared by all the examples.
+
+use std::{any::Any, cell::RefCell, collections::HashMap};
+
+use crate::{
+    broadcast_to,
+    element::Element,
+    error::TensorError,
+    operation::{MultiOperation, Operation},
+    reshape, sum_to,
+    tensor::TensorRef,
+};
+
+/// The batched tensors by id. Ids are addresses, so the tensors are held on to: a tensor dropped
+/// while `vmap` runs, e.g. an intermediate result under `no_grad`, can't pass its id on.
+type Batched = HashMap<usize, Box<dyn Any>>;
+
+thread_local! {
+    /// The batched tensors while `vmap` runs on this thread.
+    static BATCHED: RefCell<Option<Batched>> = const { RefCell::new(None) };
+}
+
+/// Whether `tensor` carries a batch axis in the `vmap` running on this thread.
+pub fn is_batched<T: Element>(tensor: &TensorRef<T>) -> bool {
+    BATCHED.with(|b| {
+        b.borrow()
+            .as_ref()
+            .is_some_and(|batched| batched.contains_key(&tensor.id()))
+    })
+}
+
+/// Marks `tensor` as batched in the `vmap` running on this thread, if any.
+pub fn mark_batched<T: Element>(tensor: &TensorRef<T>) {
+    BATCHED.with(|b| {
+        if let Some(batched) = b.borrow_mut().as_mut() {
+            batched.insert(tensor.id(), Box::new(tensor.clone()));
+        }
+    });
+}
+
+/// Sets the batched tensors until dropped, then restores the previous ones.
+struct BatchedGuard {
+    previous: Option<Batched>,
+}
+
+impl BatchedGuard {
+    fn new(batched: Option<Batched>) -> Self {
+        let previous = BATCHED.with(|b| b.replace(batched));
+        BatchedGuard { previous }
+    }
+}
+
+impl Drop for BatchedGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        BATCHED.with(|b| b.replace(previous));
+    }
+}
+
+/// Applies `op` to `inputs`, through its batching rule when some of them are batched.
+///
+/// The rule runs with batching paused, so the operations it's built from see plain arrays.
+pub fn apply<T, O>(op: &O, inputs: &[TensorRef<T>]) -> Result<TensorRef<T>, TensorError>
+where
+    T: Element,
+    O: Operation<T> + ?Sized,
+{
+    let batched: Vec<bool> = inputs.iter().map(is_batched).collect();
+    if !batched.contains(&true) {
+        return op.try_apply(inputs);
+    }
+
+    let output = {
+        let _paused = BatchedGuard::new(None);
+        op.apply_batched(inputs, &batched)?
+    };
+    mark_batched(&output);
+    Ok(output)
+}
+
+/// Same as `apply` for an operation with several outputs, all of which are batched when some of
+/// the inputs are.
+#[allow(dead_code)]
+pub fn apply_multi<T, O>(op: &O, inputs: &[TensorRef<T>]) -> Result<Vec<TensorRef<T>>, TensorError>
+where
+    T: Element,
+    O: MultiOperation<T> + Clone + 'static,
+{
+    let batched: Vec<bool> = inputs.iter().map(is_batched).collect();
+    if !batched.contains(&true) {
+        return op.try_apply(inputs);
+    }
+
+    let outputs = {
+        let _paused = BatchedGuard::new(None);
+        op.apply_batched(inputs, &batched)?
+    };
+    outputs.iter().for_each(mark_batched);
+    Ok(outputs)
+}
+
+/// Runs `f`, written for single examples, on batches: each of `inputs` holds one example per
+/// entry of its first axis, and so does the returned tensor.
+///
+/// The inputs are the caller's own tensors, so gradients of the result flow back to them as
+/// usual. `vmap` calls don't nest: calling `vmap` from `f` panics, since the inner call
+/// would take the outer batch for its own.
+pub fn vmap<T, F>(f: F, inputs: &[TensorRef<T>]) -> TensorRef<T>
+where
+    T: Element,
+    F: FnOnce(&[TensorRef<T>]) -> TensorRef<T>,
+{
+    assert!(
+        BATCHED.with(|b| b.borrow().is_none()),
+        "vmap: calls don't nest, vmap was called while another one runs"
+    );
+    let batch_sizes: Vec<usize> = inputs
+        .iter()
+        .map(|input| {
+            *input
+                .borrow()
+                .arr
+                .shape()
+                .first()
+                .expect("vmap: inputs need a batch axis")
+        })
+        .collect();
+    let batch_size = *batch_sizes.first().expect("vmap: expected some inputs");
+    assert!(
+        batch_sizes.iter().all(|&size| size == batch_size),
+        "vmap: inputs have different batch sizes {batch_sizes:?}"
+    );
+
+    let batched = inputs
+        .iter()
+        .map(|input| (input.id(), Box::new(input.clone()) as Box<dyn Any>))
+        .collect();
+    let scope = BatchedGuard::new(Some(batched));
+    let output = f(inputs);
+    let output_batched = is_batched(&output);
+    drop(scope);
+
+    if output_batched {
+        return output;
+    }
+
+    // The output doesn't depend on the batch: it's the same for every example.
+    let mut shape = output.borrow().arr.shape().to_vec();
+    shape.insert(0, batch_size);
+    broadcast_to!(output, &shape)
+}
+
+/// Size of the batch axis of the batched ones among `inputs`.
+pub fn batch_size<T: Element>(inputs: &[TensorRef<T>], batched: &[bool]) -> usize {
+    inputs
+        .iter()
+        .zip(batched)
+        .find(|(_, &batched)| batched)
+        .map(|(input, _)| input.borrow().arr.shape()[0])
+        .expect("a batching rule runs with at least one batched input")
+}
+
+/// The `inputs` with a batch axis each: the unbatched ones are repeated for every example.
+pub fn with_batch_axis<T: Element>(inputs: &[TensorRef<T>], batched: &[bool]) -> Vec<TensorRef<T>> {
+    let batch_size = batch_size(inputs, batched);
+
+    inputs
+        .iter()
+        .zip(batched)
+        .map(|(input, &batched)| {
+            if batched {
+                return input.clone();
+            }
+
+            let mut shape = vec![batch_size];
+            shape.extend(input.borrow().arr.shape());
+            broadcast_to!(input, &shape)
+        })
+        .collect()
+}
+
+/// Shape of a single example of `input`.
+pub fn example_shape<T: Element>(input: &TensorRef<T>, batched: bool) -> Vec<usize> {
+    let shape = input.borrow().arr.shape().to_vec();
+    if batched {
+        shape[1..].to_vec()
+    } else {
+        shape
+    }
+}
+
+/// Batching rule of elementwise operations, which broadcast their operands.
+///
+/// Broadcasting aligns the trailing axes of the examples, so a batched operand whose examples
+/// have fewer axes than the others gets axes of size 1 between its batch axis and the rest.
+/// Unbatched operands broadcast along the batch axis as they are.
+pub fn apply_elementwise<T, O>(
+    op: &O,
+    inputs: &[TensorRef<T>],
+    batched: &[bool],
+) -> Result<TensorRef<T>, TensorError>
+where
+    T: Element,
+    O: Operation<T> + ?Sized,
+{
+    let rank = inputs
+        .iter()
+        .zip(batched)
+        .map(|(input, &batched)| example_shape(input, batched).len())
+        .max()
+        .unwrap_or(0);
+
+    let aligned: Vec<TensorRef<T>> = inputs
+        .iter()
+        .zip(batched)
+        .map(|(input, &batched)| {
+            let shape = example_shape(input, batched);
+            if !batched || shape.len() == rank {
+                return input.clone();
+            }
+
+            let batch_size = input.borrow().arr.shape()[0];
+            let mut aligned_shape = vec![batch_size];
+            aligned_shape.resize(rank - shape.len() + 1, 1);
+            aligned_shape.extend(shape);
+            reshape!(input, &aligned_shape)
+        })
+        .collect();
+
+    op.try_apply(&aligned)
+}
+
+/// Sums every example of the batched `input` down to a single value, keeping the example axes
+/// with size 1 so the result broadcasts against `input`.
+pub fn sum_examples<T: Element>(input: &TensorRef<T>) -> TensorRef<T> {
+    let shape = input.borrow().arr.shape().to_vec();
+    let mut summed_shape = vec![1; shape.len()];
+    summed_shape[0] = shape[0];
+
+    sum_to!(input, &summed_shape)
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, Array, ArrayBase, ArrayD, Axis, Data, IxDyn};
+
+    use crate::{
+        add, broadcast_to, grad_mode::no_grad, ln, matmul, prod, softmax, sum, sum_to, tanh,
+        tensor, tensor::TensorRef, transpose,
+    };
+
+    use super::vmap;
+
+    /// Applies `f` to each example of `inputs` separately and stacks the results.
+    fn per_example<F>(f: F, inputs: &[TensorRef]) -> ArrayD<f64>
+    where
+        F: Fn(&[TensorRef]) -> TensorRef,
+    {
+        let batch_size = inputs[0].borrow().arr.shape()[0];
+        let outputs: Vec<ArrayD<f64>> = (0..batch_size)
+            .map(|i| {
+                let examples: Vec<TensorRef> = inputs
+                    .iter()
+                    .map(|input| tensor!(input.borrow().arr.index_axis(Axis(0), i).to_owned()))
+                    .collect();
+                f(&examples).borrow().arr.to_owned()
+            })
+            .collect();
+        let views: Vec<_> = outputs.iter().map(|o| o.view()).collect();
+
+        ndarray::stack(Axis(0), &views).unwrap()
+    }
+
+    fn assert_close(
+        actual: &ArrayBase<impl Data<Elem = f64>, IxDyn>,
+        expected: &ArrayBase<impl Data<Elem = f64>, IxDyn>,
+    ) {
+        assert_eq!(actual.shape(), expected.shape());
+        assert!(actual
+            .iter()
+            .zip(expected)
+            .all(|(a, e)| (a - e).abs() < 1e-12));
+    }
+
+    #[test]
+    fn batched_mlp_matches_examples() {
+        let w = tensor!(array![[0.1, -0.2], [0.3, 0.4], [-0.5, 0.6]]);
+        let b = tensor!(array![[0.1], [0.0], [-0.1]]);
+        let xs = tensor!(Array::linspace(-1.0, 1.0, 8)
+            .into_shape_with_order((4, 2, 1))
+            .unwrap());
+        let labels = tensor!(array![
+            [[1.0], [0.0], [0.0]],
+            [[0.0], [1.0], [0.0]],
+            [[0.0], [0.0], [1.0]],
+            [[0.0], [1.0], [0.0]]
+        ]);
+
+        let loss = |v: &[TensorRef]| {
+            let probs = softmax!(tanh!(add!(matmul!(w, v[0]), b)));
+            prod!(sum!(prod!(ln!(probs), v[1])), -1.0)
+        };
+
+        let batched = vmap(loss, &[xs.clone(), labels.clone()]);
+        let expected = per_example(loss, &[xs, labels]);
+        assert_close(&batched.borrow().arr, &expected);
+
+        // Gradients of the batched graph add up the gradients of every example.
+        sum!(batched).backward(None);
+        let batched_grad = w.borrow().grad().unwrap().arr.clone();
+        w.zero_grad();
+        for i in 0..4 {
+            let x = tensor!(Array::linspace(-1.0, 1.0, 8)
+                .into_shape_with_order((4, 2, 1))
+                .unwrap()
+                .index_axis(Axis(0), i)
+                .to_owned());
+            let label = tensor!(ArrayD::from_shape_fn(vec![3, 1], |idx| {
+                let classes = [0, 1, 2, 1];
+                if idx[0] == classes[i] {
+                    1.0
+                } else {
+                    0.0
+                }
+            }));
+            loss(&[x, label]).backward(None);
+        }
+        assert_close(&batched_grad, &w.borrow().grad().unwrap().arr);
+    }
+
+    #[test]
+    fn batched_operands_on_both_sides() {
+        let a = tensor!(Array::linspace(0.0, 1.0, 12)
+            .into_shape_with_order((2, 2, 3))
+            .unwrap());
+        let b = tensor!(Array::linspace(-1.0, 0.0, 4)
+            .into_shape_with_order((2, 2, 1))
+            .unwrap());
+        let row = tensor!(Array::linspace(1.0, 2.0, 6)
+            .into_shape_with_order((2, 3))
+            .unwrap());
+
+        let f = |v: &[TensorRef]| {
+            let product = matmul!(transpose!(v[0]), v[1]);
+            let spread = add!(broadcast_to!(product, &[3, 3]), v[2]);
+            sum_to!(spread, &[3])
+        };
+
+        let batched = vmap(f, &[a.clone(), b.clone(), row.clone()]);
+        let expected = per_example(f, &[a, b, row]);
+        assert_close(&batched.borrow().arr, &expected);
+    }
+
+    #[test]
+    fn dropped_intermediates_under_no_grad_stay_unbatched() {
+        let w = tensor!(array![[0.5, -1.0], [0.25, 2.0]]);
+        let xs = tensor!(Array::linspace(-1.0, 1.0, 6)
+            .into_shape_with_order((3, 2, 1))
+            .unwrap());
+
+        // Without a graph the intermediates are dropped as soon as they're used, and new tensors
+        // computed from `w` alone can be allocated where batched ones were.
+        let f = |v: &[TensorRef]| {
+            let h = tanh!(add!(v[0], 1.0));
+            sum!(add!(matmul!(add!(prod!(w, 2.0), 0.0), h), 0.0))
+        };
+        let batched = no_grad(|| vmap(f, std::slice::from_ref(&xs)));
+        let expected = no_grad(|| per_example(f, &[xs]));
+
+        assert_close(&batched.borrow().arr, &expected);
+    }
+
+    #[test]
+    fn unbatched_output_is_repeated() {
+        let xs = tensor!(array![[1.0], [2.0]]);
+        let c = tensor!(5.0);
+
+        let out = vmap(|_| prod!(c, 2.0), &[xs]);
+
+        assert_eq!(out.borrow().arr, array![[[10.0]], [[10.0]]].into_dyn());
+    }
+
+    #[test]
+    #[should_panic(expected = "vmap: calls don't nest")]
+    fn nested_vmap_panics() {
+        let xs = tensor!(array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+
+        vmap(|v| vmap(|w| sum!(w[0]), v), &[xs]);
+    }
+}